@@ -1,6 +1,15 @@
+use std::num::NonZeroUsize;
+
 use clap::{Parser, Subcommand};
+use sov_core::graph::GraphFilter;
+use sov_core::lint::Fix;
+use sov_core::note::LinkKind;
+use sov_core::query::QuerySort;
+use sov_core::tag::TagSort;
 use sov_core::SovFeature;
 
+use crate::output::OutputFormat;
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct SovArgs {
@@ -8,17 +17,35 @@ pub struct SovArgs {
     pub cmd: SovCmd,
     #[arg(short, long)]
     pub silent: bool,
+    /// Output format of the results
+    #[arg(short, long, global = true, value_enum, default_value_t)]
+    pub format: OutputFormat,
 }
 
 #[derive(Subcommand, Debug)]
 pub enum SovCmd {
+    /// Index the notes dir and print the notes that could not be fully indexed
     Index,
+    /// Keep the index up to date as notes change on disk
+    Watch,
     List {
         #[command(subcommand)]
         cmd: ListCommand,
     },
+    /// Print the path of a note, or `path:line` for `Note#Heading` and
+    /// `Note#^block-id`
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
     Resolve {
+        #[arg(required = true)]
+        note: Option<String>,
+        #[command(subcommand)]
+        cmd: Option<ResolveCommand>,
+    },
+    /// Add a `^block-id` at the end of a line and print the link to it
+    Block {
         note: String,
+        /// 1-based line number
+        line: NonZeroUsize,
     },
     Rename {
         old_filename: String,
@@ -28,23 +55,143 @@ pub enum SovCmd {
         #[command(subcommand)]
         cmd: ScriptCommand,
     },
+    /// Rename or merge tags in the frontmatter and the content of all notes
+    Tag {
+        #[command(subcommand)]
+        cmd: TagCommand,
+    },
     Search {
         #[command(subcommand)]
         cmd: SearchCommand,
     },
+    /// List the notes matching a query, e.g.
+    /// `tag:project AND prop.status=open AND NOT links-to:Roadmap`.
+    /// Terms: `tag:`, `prop.<key>`, `prop.<key>=<value>` (also `!=`, `<`,
+    /// `<=`, `>`, `>=`), `links-to:`, `path:`, `text:`
+    Query {
+        query: String,
+        /// Sort by path, name or modified (most recent first)
+        #[arg(long, default_value = "path")]
+        sort: QuerySort,
+        /// Maximum number of notes to list
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// List the notes matching a saved query of the `[queries]` config table
+    RunQuery {
+        name: String,
+        /// Sort by path, name or modified (most recent first)
+        #[arg(long, default_value = "path")]
+        sort: QuerySort,
+        /// Maximum number of notes to list
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// Check the notes for problems (invalid frontmatter, dead links, ...),
+    /// exits with 1 if a problem of the `error` level is found. Rule levels
+    /// are set in the `[lints]` config table
+    Doctor {
+        /// Also exit with 1 for problems of the `warning` level
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Apply automatic fixes: `tags` rewrites the frontmatter tags as a list,
+    /// `link-case` rewrites links only matching case-insensitively or once
+    /// normalized, `ambiguous-links` path-qualifies ambiguous links to the
+    /// closest note. The diff is printed before asking for confirmation
+    Fix {
+        /// Fixes to apply, all by default
+        #[arg(long, value_delimiter = ',')]
+        only: Vec<Fix>,
+        /// Create a stub note for a dead link, e.g. `--stub "Some Note"`
+        #[arg(long)]
+        stub: Vec<String>,
+        /// Print the diff of the changes without writing them
+        #[arg(long)]
+        dry_run: bool,
+        /// Write the changes without asking for confirmation
+        #[arg(long, short, conflicts_with = "dry_run")]
+        yes: bool,
+    },
+    /// Export the graph of the notes and their links
+    Graph {
+        #[command(subcommand)]
+        cmd: GraphCommand,
+    },
     Daily,
 }
 
 #[derive(Subcommand, Debug)]
 pub enum ListCommand {
-    Tags,
+    Tags {
+        /// Print nested tags as a tree, with the number of notes of each tag
+        #[arg(long)]
+        tree: bool,
+        /// Sort by name, count (most used first) or modified (tags of the most
+        /// recently modified notes first)
+        #[arg(long, default_value = "name", conflicts_with = "tree")]
+        sort: TagSort,
+        /// Only list tags used by at least this number of notes
+        #[arg(long, default_value_t = 0, conflicts_with = "tree")]
+        min_count: usize,
+    },
     /// Orphans are notes that are not linked to any other note
     Orphans,
     /// Dead links are notes that are linked to, but do not exist
     DeadLinks,
+    /// Notes sharing their filename with other notes, links to them must be
+    /// path-qualified, e.g. `[[projects/sov/index]]`
+    Duplicates,
+    /// Frontmatter property keys, with the number of notes using them
+    Props,
+    /// Links of a note
+    Links {
+        note: String,
+        /// Only list links of this kind: link, embed, image_embed or markdown
+        #[arg(long)]
+        kind: Option<LinkKind>,
+    },
+    /// Links pointing to a note
+    Backlinks {
+        note: String,
+        /// Only list links of this kind: link, embed, image_embed or markdown
+        #[arg(long)]
+        kind: Option<LinkKind>,
+    },
     Scripts,
 }
 
+#[derive(Subcommand, Debug)]
+pub enum GraphCommand {
+    /// Print the graph of the notes in the DOT (default), GraphML or JSON
+    /// format, e.g. `sov graph export --format dot | dot -Tsvg > graph.svg`
+    Export {
+        /// Only notes with this tag or one of its nested tags
+        #[arg(long)]
+        tag: Option<String>,
+        /// Only notes under this path, relative to the notes dir
+        #[arg(long)]
+        path: Option<String>,
+        /// Only notes within `--depth` links of this note, in either direction
+        #[arg(long)]
+        root: Option<String>,
+        #[arg(long, default_value_t = 1, requires = "root")]
+        depth: usize,
+        /// Include the missing notes of dead links, as dashed nodes in DOT
+        #[arg(long)]
+        dead_links: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ResolveCommand {
+    /// Plain-text mentions of a note's filename and aliases in the other
+    /// notes, with the wiki-link to replace them with
+    UnlinkedMentions {
+        note: String,
+    },
+}
+
 #[derive(Subcommand, Debug)]
 pub enum ScriptCommand {
     Run {
@@ -58,25 +205,105 @@ pub enum ScriptCommand {
     },
 }
 
+#[derive(Subcommand, Debug)]
+pub enum TagCommand {
+    /// Rename a tag, nested tags included
+    Rename {
+        old_tag: String,
+        new_tag: String,
+        /// Print the diff of the changes without writing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Merge several tags into one, nested tags included
+    Merge {
+        #[arg(required = true)]
+        tags: Vec<String>,
+        #[arg(long)]
+        into: String,
+        /// Print the diff of the changes without writing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
 #[derive(Subcommand, Debug)]
 pub enum SearchCommand {
     Tag {
         name: String
     },
+    /// Full-text search over note titles, aliases and content
+    Text {
+        query: String,
+    },
+    /// Notes with a frontmatter property, e.g. `status=draft` or `status`
+    Prop {
+        filter: String,
+    },
 }
 
 impl From<SovCmd> for SovFeature {
     fn from(cmd: SovCmd) -> Self {
         match cmd {
             SovCmd::Index => SovFeature::Index,
+            SovCmd::Watch => SovFeature::Watch,
             SovCmd::List { cmd } => match cmd {
-                ListCommand::Tags => SovFeature::ListTags,
+                ListCommand::Tags {
+                    tree,
+                    sort,
+                    min_count,
+                } => SovFeature::ListTags {
+                    tree,
+                    sort,
+                    min_count,
+                },
                 ListCommand::Orphans => SovFeature::ListOrphans,
                 ListCommand::DeadLinks => SovFeature::ListDeadLinks,
+                ListCommand::Duplicates => SovFeature::ListDuplicates,
+                ListCommand::Props => SovFeature::ListProperties,
+                ListCommand::Links { note, kind } => SovFeature::ResolveLinks { note, kind },
+                ListCommand::Backlinks { note, kind } => {
+                    SovFeature::ResolveBacklinks { note, kind }
+                }
                 ListCommand::Scripts => SovFeature::ListScripts,
             },
-            SovCmd::Resolve { note } => SovFeature::ResolveNote { note },
+            SovCmd::Resolve { note, cmd } => match cmd {
+                Some(ResolveCommand::UnlinkedMentions { note }) => {
+                    SovFeature::ResolveUnlinkedMentions { note }
+                }
+                None => SovFeature::ResolveNote {
+                    note: note.unwrap_or_default(),
+                },
+            },
+            SovCmd::Block { note, line } => SovFeature::InsertBlockId {
+                note,
+                line: line.get() - 1,
+            },
             SovCmd::Rename { old_filename, new_filename } => SovFeature::Rename { old_filename, new_filename },
+            SovCmd::Query { query, sort, limit } => SovFeature::Query { query, sort, limit },
+            SovCmd::RunQuery { name, sort, limit } => SovFeature::RunQuery { name, sort, limit },
+            SovCmd::Doctor { strict } => SovFeature::Doctor { strict },
+            SovCmd::Fix { only, stub, .. } => SovFeature::Fix {
+                fixes: only,
+                stubs: stub,
+            },
+            SovCmd::Graph { cmd } => match cmd {
+                GraphCommand::Export {
+                    tag,
+                    path,
+                    root,
+                    depth,
+                    dead_links,
+                } => SovFeature::GraphExport {
+                    filter: GraphFilter {
+                        tag,
+                        path,
+                        root,
+                        depth,
+                        dead_links,
+                    },
+                },
+            },
             SovCmd::Daily => SovFeature::Daily,
             SovCmd::Script { cmd } => match cmd {
                 ScriptCommand::Run { script_name, args } => {
@@ -92,8 +319,39 @@ impl From<SovCmd> for SovFeature {
                     args,
                 },
             },
+            SovCmd::Tag { cmd } => match cmd {
+                TagCommand::Rename {
+                    old_tag,
+                    new_tag,
+                    dry_run,
+                } => SovFeature::RenameTag {
+                    old_tag,
+                    new_tag,
+                    dry_run,
+                },
+                TagCommand::Merge {
+                    tags,
+                    into,
+                    dry_run,
+                } => SovFeature::MergeTags {
+                    tags,
+                    into,
+                    dry_run,
+                },
+            },
             SovCmd::Search { cmd } => match cmd {
                 SearchCommand::Tag { name } => SovFeature::SearchTag { tag: name },
+                SearchCommand::Text { query } => SovFeature::SearchText { query },
+                SearchCommand::Prop { filter } => match filter.split_once('=') {
+                    Some((key, value)) => SovFeature::SearchProperty {
+                        key: key.to_string(),
+                        value: Some(value.to_string()),
+                    },
+                    None => SovFeature::SearchProperty {
+                        key: filter,
+                        value: None,
+                    },
+                },
             },
         }
     }
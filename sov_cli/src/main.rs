@@ -1,24 +1,38 @@
 mod args;
+mod output;
 
-use args::{ScriptCommand, SearchCommand};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use args::{GraphCommand, ResolveCommand, ScriptCommand, SearchCommand, TagCommand};
 use clap::Parser;
-use color_eyre::eyre::eyre;
 use color_eyre::Result;
+use sov_core::graph::GraphFilter;
+use sov_core::lint::{Fix, Lint, LintLevel, LintRule};
+use sov_core::note::{Link, LinkRef, Mention, NoteRef};
+use sov_core::tag::TagNode;
 use sov_core::Sov;
-use tracing::Level;
+use tracing::{error, info, warn, Level};
 use tracing_subscriber::prelude::*;
 
 use crate::args::{ListCommand, SovArgs, SovCmd};
+use crate::output::OutputFormat;
 
 pub fn main() -> Result<()> {
     color_eyre::install()?;
     let args = SovArgs::parse();
 
     if !args.silent {
-        // Setup logging
+        // Setup logging, on stderr to keep the results parsable. Scripts
+        // consuming machine-readable formats only need the warnings.
+        let level = match args.format {
+            OutputFormat::Text => Level::INFO,
+            _ => Level::WARN,
+        };
         tracing_subscriber::fmt()
-            .with_max_level(Level::INFO)
+            .with_max_level(level)
             .with_target(true)
+            .with_writer(std::io::stderr)
             .finish()
             .init();
     }
@@ -26,77 +40,274 @@ pub fn main() -> Result<()> {
     // TODO: Use the feature instead?
     //let sov_feature = args.cmd.into();
 
-    let mut sov = Sov::new()?;
+    let mut sov = Sov::load()?;
+    // Progress is reported from several threads, only print it in order
+    let printed = Mutex::new(0);
+    let report = sov.index_with_progress(|p| {
+        let mut printed = printed.lock().unwrap();
+        if !args.silent && p.parsed > *printed {
+            *printed = p.parsed;
+            eprint!("\rIndexing notes: {}/{}", p.parsed, p.total);
+            if p.parsed == p.total {
+                eprintln!();
+            }
+        }
+    })?;
+    let format = args.format;
+    // Invalid frontmatters are reported as lints by `sov doctor`
+    if !matches!(args.cmd, SovCmd::Index | SovCmd::Doctor { .. }) {
+        for e in &report.errors {
+            warn!("Failed to index {:?}: {}", e.path, e.error);
+        }
+    }
     match args.cmd {
-        SovCmd::Index => sov.index()?,
-        SovCmd::List { cmd } => match cmd {
-            ListCommand::Tags => {
-                let tags = sov.list_tags()?;
-                for tag in tags {
-                    println!("{}", tag);
+        // The notes dir is always indexed on startup
+        SovCmd::Index => {
+            format.print_list(&report.errors, |e| {
+                format!("{}: {}", e.path.display(), e.error)
+            })?;
+        }
+        SovCmd::Watch => {
+            let watcher = sov.watcher()?;
+            info!("Watching for changes ...");
+            while let Some(paths) = watcher.next_changes() {
+                match sov.index_paths(&paths) {
+                    Ok(report) => {
+                        for e in report.errors {
+                            warn!("Failed to index {:?}: {}", e.path, e.error);
+                        }
+                    }
+                    Err(e) => error!("Failed to index changes: {}", e),
                 }
             }
+        }
+        SovCmd::List { cmd } => match cmd {
+            ListCommand::Tags {
+                tree: false,
+                sort,
+                min_count,
+            } => {
+                let tags = sov.list_tags(sort, min_count)?;
+                format.print_list(&tags, |t| {
+                    format!(
+                        "{} ({}, {} - {})",
+                        t.tag, t.count, t.oldest_modified, t.newest_modified
+                    )
+                })?;
+            }
+            ListCommand::Tags { tree: true, .. } => {
+                let tree = sov.list_tag_tree()?;
+                format.print_list(&tree, |node| tag_tree_text(node, 0))?;
+            }
             ListCommand::Orphans => {
-                let orphans = sov.list_orphans()?;
-                for orphan in orphans {
-                    println!("{}", orphan.to_str().ok_or(eyre!("path error"))?);
-                }
+                let orphans = note_refs(sov.list_orphans()?);
+                format.print_list(&orphans, NoteRef::to_string)?;
             }
             ListCommand::DeadLinks => {
-                let res = sov.list_dead_links()?;
-                for (path, dead_link) in res {
-                    println!(
-                        "{}: {}",
-                        path.to_str().ok_or(eyre!("path error"))?,
-                        dead_link
-                    );
-                }
+                let dead_links = link_refs(sov.list_dead_links()?);
+                format.print_list(&dead_links, LinkRef::to_string)?;
+            }
+            ListCommand::Duplicates => {
+                let duplicates = sov.list_duplicates()?;
+                format.print_list(&duplicates, |d| {
+                    let paths: Vec<_> = d.paths.iter().map(|p| p.display().to_string()).collect();
+                    format!("{}: {}", d.filename, paths.join(", "))
+                })?;
+            }
+            ListCommand::Props => {
+                let props = sov.list_properties()?;
+                format.print_list(&props, |p| format!("{} ({})", p.key, p.count))?;
+            }
+            ListCommand::Links { note, kind } => {
+                let links = sov.resolve_links(&note, kind)?;
+                format.print_list(&links, Link::to_string)?;
+            }
+            ListCommand::Backlinks { note, kind } => {
+                let backlinks = link_refs(sov.resolve_backlinks(&note, kind)?);
+                format.print_list(&backlinks, LinkRef::to_string)?;
             }
             ListCommand::Scripts => {
                 let scripts = sov.list_scripts()?;
-                for script in scripts {
-                    println!("{}", script);
-                }
+                format.print_list(&scripts, String::clone)?;
             }
         },
-        SovCmd::Resolve { note } => {
-            let path = sov.resolve_note(&note)?;
-            let Some(path) = path else {
-                return Ok(());
+        SovCmd::Resolve {
+            cmd: Some(ResolveCommand::UnlinkedMentions { note }),
+            ..
+        } => {
+            let mentions = sov.resolve_unlinked_mentions(&note)?;
+            format.print_list(&mentions, Mention::to_string)?;
+        }
+        SovCmd::Resolve { note, .. } => {
+            let note = note.unwrap_or_default();
+            let note_ref = if let Some((note, block)) = note.split_once("#^") {
+                sov.resolve_block(note, block)?
+                    .map(|(path, block)| NoteRef::new(path).at_line(block.line))
+            } else if let Some((note, header)) = note.split_once('#') {
+                sov.resolve_heading(note, header)?
+                    .map(|(path, heading)| NoteRef::new(path).at_line(heading.start))
+            } else {
+                sov.resolve_note(&note)?.map(NoteRef::new)
             };
-            println!("{}", path.display());
+            format.print(note_ref.as_ref(), NoteRef::to_string)?;
+        }
+        SovCmd::Block { note, line } => {
+            let id = sov.insert_block_id(&note, line.get() - 1)?;
+            let link = format!("[[{}#^{}]]", note, id);
+            format.print(Some(&link), String::clone)?;
         }
+        SovCmd::Graph { cmd } => match cmd {
+            GraphCommand::Export {
+                tag,
+                path,
+                root,
+                depth,
+                dead_links,
+            } => {
+                let filter = GraphFilter {
+                    tag,
+                    path,
+                    root,
+                    depth,
+                    dead_links,
+                };
+                let graph = sov.graph(&filter)?;
+                match format {
+                    OutputFormat::Text | OutputFormat::Dot => print!("{}", graph.to_dot()),
+                    OutputFormat::Graphml => print!("{}", graph.to_graphml()),
+                    _ => format.print(Some(&graph), |_| String::new())?,
+                }
+            }
+        },
         SovCmd::Daily => {
-            let note = sov.daily()?;
-            dbg!(note);
+            let note = NoteRef::new(sov.daily()?);
+            format.print(Some(&note), NoteRef::to_string)?;
         }
         SovCmd::Script { cmd } => match cmd {
             ScriptCommand::Run { script_name, args } => {
                 let res = sov.script_run(&script_name, args)?;
-                println!("{}", res);
+                format.print(Some(&res), String::clone)?;
             }
             ScriptCommand::Create {
                 note_name,
                 script_name,
                 args,
             } => {
-                let note_path = sov.script_create(&note_name, &script_name, args)?;
-                println!("{:?}", note_path);
+                let note = NoteRef::new(sov.script_create(&note_name, &script_name, args)?);
+                format.print(Some(&note), NoteRef::to_string)?;
             }
         },
+        SovCmd::Tag { cmd } => {
+            let (edits, dry_run) = match cmd {
+                TagCommand::Rename {
+                    old_tag,
+                    new_tag,
+                    dry_run,
+                } => (sov.rename_tag(&old_tag, &new_tag, dry_run)?, dry_run),
+                TagCommand::Merge {
+                    tags,
+                    into,
+                    dry_run,
+                } => (sov.merge_tags(&tags, &into, dry_run)?, dry_run),
+            };
+            format.print_list(&edits, |edit| match dry_run {
+                true => edit.diff.trim_end().to_string(),
+                false => format!("Updated {}", edit.path.display()),
+            })?;
+        }
         SovCmd::Search { cmd } => match cmd {
             SearchCommand::Tag { name } => {
-                let notes = sov.search_tag(&name)?;
-                for note in notes {
-                    println!("{}", note.display());
-                }
+                let notes = note_refs(sov.search_tag(&name)?);
+                format.print_list(&notes, NoteRef::to_string)?;
+            }
+            SearchCommand::Prop { filter } => {
+                let (key, value) = match filter.split_once('=') {
+                    Some((key, value)) => (key, Some(value)),
+                    None => (filter.as_str(), None),
+                };
+                let notes = note_refs(sov.search_property(key, value)?);
+                format.print_list(&notes, NoteRef::to_string)?;
+            }
+            SearchCommand::Text { query } => {
+                let matches = sov.search_text(&query)?;
+                format.print_list(&matches, |m| {
+                    format!("{}:{}: {}", m.path.display(), m.line, m.snippet)
+                })?;
             }
         },
+        SovCmd::Query { query, sort, limit } => {
+            let notes = note_refs(sov.query(&query, sort, limit)?);
+            format.print_list(&notes, NoteRef::to_string)?;
+        }
+        SovCmd::RunQuery { name, sort, limit } => {
+            let notes = note_refs(sov.run_query(&name, sort, limit)?);
+            format.print_list(&notes, NoteRef::to_string)?;
+        }
+        SovCmd::Doctor { strict } => {
+            let lints = sov.lint()?;
+            // Unreadable notes are not indexed, so they have no lints
+            for e in &report.errors {
+                let is_lint = |l: &Lint| l.rule == LintRule::InvalidFrontmatter && l.path == e.path;
+                if !lints.iter().any(is_lint) {
+                    warn!("Failed to index {:?}: {}", e.path, e.error);
+                }
+            }
+            format.print_list(&lints, Lint::to_string)?;
+            if LintLevel::fails(&lints, strict) {
+                std::process::exit(1);
+            }
+        }
+        SovCmd::Fix {
+            only,
+            stub,
+            dry_run,
+            yes,
+        } => {
+            let fixes = if only.is_empty() { &Fix::ALL[..] } else { &only };
+            let edits = sov.fix(fixes, &stub)?;
+            format.print_list(&edits, |edit| edit.diff.trim_end().to_string())?;
+            if dry_run || edits.is_empty() {
+                return Ok(());
+            }
+            if !yes {
+                eprint!("Apply the changes to {} notes? [y/N] ", edits.len());
+                let mut answer = String::new();
+                std::io::stdin().read_line(&mut answer)?;
+                if !matches!(answer.trim(), "y" | "Y" | "yes") {
+                    return Ok(());
+                }
+            }
+            for edit in &edits {
+                info!("Updating {:?}", edit.path);
+                edit.write()?;
+            }
+        }
         SovCmd::Rename { old_filename, new_filename } => {
             let new_path = sov.rename_file(&old_filename, &new_filename, true)?;
-            println!("Successfully renamed {} to {}", old_filename, new_path.display());
+            let note = NoteRef::new(new_path);
+            format.print(Some(&note), |note| {
+                format!("Successfully renamed {} to {}", old_filename, note)
+            })?;
         }
     };
 
     Ok(())
 }
+
+fn note_refs(paths: Vec<PathBuf>) -> Vec<NoteRef> {
+    paths.into_iter().map(NoteRef::from).collect()
+}
+
+fn link_refs(links: Vec<(PathBuf, Link)>) -> Vec<LinkRef> {
+    links.into_iter().map(LinkRef::from).collect()
+}
+
+/// A tag and its nested tags, indented by depth
+fn tag_tree_text(node: &TagNode, depth: usize) -> String {
+    let mut text = format!("{}{} ({})", "  ".repeat(depth), node.name, node.count);
+    for child in &node.children {
+        text.push('\n');
+        text.push_str(&tag_tree_text(child, depth + 1));
+    }
+    text
+}
@@ -0,0 +1,59 @@
+use clap::ValueEnum;
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+use serde::Serialize;
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum OutputFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// A single JSON document
+    Json,
+    /// One JSON document per line
+    Jsonl,
+    /// Graphviz DOT language, only for `sov graph export`
+    Dot,
+    /// GraphML, only for `sov graph export`
+    Graphml,
+}
+
+impl OutputFormat {
+    /// Prints a list of results, `text` formats one item in text format
+    pub fn print_list<T: Serialize>(&self, items: &[T], text: impl Fn(&T) -> String) -> Result<()> {
+        match self {
+            OutputFormat::Text => {
+                for item in items {
+                    println!("{}", text(item));
+                }
+            }
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(items)?),
+            OutputFormat::Jsonl => {
+                for item in items {
+                    println!("{}", serde_json::to_string(item)?);
+                }
+            }
+            OutputFormat::Dot | OutputFormat::Graphml => return Err(Self::graph_only()),
+        }
+        Ok(())
+    }
+
+    /// Prints a single result, nothing is printed in text format for `None`
+    pub fn print<T: Serialize>(&self, item: Option<&T>, text: impl Fn(&T) -> String) -> Result<()> {
+        match self {
+            OutputFormat::Text => {
+                if let Some(item) = item {
+                    println!("{}", text(item));
+                }
+            }
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&item)?),
+            OutputFormat::Jsonl => println!("{}", serde_json::to_string(&item)?),
+            OutputFormat::Dot | OutputFormat::Graphml => return Err(Self::graph_only()),
+        }
+        Ok(())
+    }
+
+    fn graph_only() -> color_eyre::Report {
+        eyre!("the dot and graphml formats are only supported by `sov graph export`")
+    }
+}
@@ -1,14 +1,15 @@
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
-use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
 
 use crate::error::{Result, SovError};
+use crate::lint::{LintLevel, LintRule};
+use crate::query::Query;
 
 pub struct SovConfig {
     pub config_dir: PathBuf,
-    pub last_update_path: PathBuf,
-    pub last_update: DateTime<Utc>,
     pub db_path: PathBuf,
     pub toml_path: PathBuf,
     pub toml: SovConfigToml,
@@ -21,12 +22,62 @@ pub struct SovConfigToml {
     pub daily_notes_script: String,
     pub scripts_dir: PathBuf,
     pub ignore_dirs: Vec<PathBuf>,
+    /// Saved queries, by name, see [`crate::query::Query`]
+    #[serde(default)]
+    pub queries: BTreeMap<String, String>,
+    #[serde(default)]
+    pub link_matching: LinkMatching,
+    /// Levels of the lint rules overriding their default level, see
+    /// [`LintRule::default_level`]
+    #[serde(default)]
+    pub lints: BTreeMap<LintRule, LintLevel>,
+}
+
+/// How link values are matched with note filenames and aliases, e.g. to match
+/// `[[my note]]` with `My Note.md`
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LinkMatching {
+    pub case_insensitive: bool,
+    /// Matches the composed (NFC) and decomposed (NFD) forms of a name, e.g.
+    /// filenames created on Linux and macOS
+    pub unicode_normalization: bool,
+}
+
+impl LinkMatching {
+    /// The key under which `name` is matched, equal for all the names
+    /// matching each other
+    pub fn key(&self, name: &str) -> String {
+        let name: String = match self.unicode_normalization {
+            true => name.nfc().collect(),
+            false => name.to_string(),
+        };
+        match self.case_insensitive {
+            true => name.to_lowercase(),
+            false => name,
+        }
+    }
+
+    /// Whether names are matched exactly
+    pub fn is_exact(&self) -> bool {
+        *self == LinkMatching::default()
+    }
+}
+
+impl SovConfigToml {
+    /// Checks that all the saved queries parse, so that an invalid query is
+    /// reported on load instead of when it is run
+    fn check_queries(&self) -> Result<()> {
+        for (name, query) in &self.queries {
+            Query::parse(query)
+                .map_err(|e| SovError::InvalidSavedQuery(name.clone(), Box::new(e)))?;
+        }
+        Ok(())
+    }
 }
 
 impl SovConfig {
     pub const SOV_DIR: &'static str = "sov";
-    pub const LAST_UPDATE_FILE: &'static str = "last_update";
-    pub const MIN_DATE: &'static str = "1900-01-01T00:00:00+00:00";
     pub const DB_FILE: &'static str = "sov.db3";
 
     pub fn load() -> Result<Self> {
@@ -36,17 +87,6 @@ impl SovConfig {
             std::fs::create_dir_all(&config_dir)?;
         }
 
-        let last_update_path = config_dir.join(Self::LAST_UPDATE_FILE);
-        let last_update = if !last_update_path.exists() {
-            std::fs::write(&last_update_path, Self::MIN_DATE)?;
-            Self::MIN_DATE.to_string()
-        } else {
-            std::fs::read_to_string(&last_update_path)?
-                .trim()
-                .to_string()
-        };
-        let last_update = DateTime::parse_from_rfc3339(&last_update)?.to_utc();
-
         let db_path = config_dir.join(Self::DB_FILE);
 
         let toml_path = config_dir.join("sov.toml");
@@ -60,6 +100,7 @@ impl SovConfig {
             toml
         };
 
+        toml.check_queries()?;
         if toml.notes_dir.as_os_str().is_empty() {
             return Err(SovError::NoNotesDir);
         }
@@ -81,18 +122,26 @@ impl SovConfig {
 
         Ok(Self {
             config_dir,
-            last_update_path,
-            last_update,
             db_path,
             toml_path,
             toml,
         })
     }
+}
 
-    pub fn update_last_update(&mut self) -> Result<()> {
-        let now = chrono::offset::Utc::now();
-        std::fs::write(&self.last_update_path, now.to_rfc3339())?;
-        self.last_update = now;
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_saved_query() {
+        let mut toml = SovConfigToml::default();
+        toml.queries.insert("todo".into(), "tag:todo".into());
+        assert!(toml.check_queries().is_ok());
+
+        toml.queries
+            .insert("broken".into(), "tag:a AND (tag:b".into());
+        let err = toml.check_queries().unwrap_err();
+        assert!(matches!(err, SovError::InvalidSavedQuery(ref name, _) if name == "broken"));
     }
 }
@@ -1,10 +1,15 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 
-use rusqlite::{params, Connection, OptionalExtension};
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Params, Row, ToSql};
+use tracing::info;
 
+use crate::config::LinkMatching;
 use crate::error::{Result, SovError};
-use crate::note::Link;
+use crate::note::{Block, Heading, Link, LinkKind, NoteError, NoteStat, PropertyCount, TextMatch};
+use crate::query::{QuerySort, QuerySql};
+use crate::tag::TagUsage;
 use crate::SovNote;
 
 pub struct SovDb {
@@ -12,8 +17,50 @@ pub struct SovDb {
 }
 
 impl SovDb {
-    pub fn new(path: &PathBuf) -> Result<Self> {
-        let db = Connection::open(path)?;
+    /// Bump this whenever `db.sql` changes in a non backward-compatible way, or
+    /// when notes are parsed differently, as unmodified notes are not re-parsed
+    const SCHEMA_VERSION: u32 = 12;
+
+    /// Columns of the `link` table (aliased as `l`) read by
+    /// [`Self::link_from_row`]
+    const LINK_COLUMNS: &'static str =
+        "l.kind, l.link_value, l.alias, l.header, l.block, l.path, l.start, l.end";
+
+    /// Matches the rows of the `link` table that point to a missing note, a
+    /// missing heading or a missing block. Images are not notes so image
+    /// embeds are never dead.
+    const DEAD_LINK_COND: &'static str = "
+        l.kind != 'image_embed' AND (l.targets = 0
+        OR (l.header IS NOT NULL AND l.target_note IS NOT NULL AND NOT EXISTS (
+            SELECT 1 FROM heading h
+            WHERE h.note_id = l.target_note
+            AND (h.text = l.header COLLATE NOCASE OR h.slug = l.header)
+        ))
+        OR (l.block IS NOT NULL AND l.target_note IS NOT NULL AND NOT EXISTS (
+            SELECT 1 FROM block b
+            WHERE b.note_id = l.target_note AND b.block_id = l.block
+        )))";
+
+    pub fn new(path: &PathBuf, link_matching: LinkMatching) -> Result<Self> {
+        let mut db = Connection::open(path)?;
+        let version: u32 = db.query_row("PRAGMA user_version", [], |r| r.get(0))?;
+        let matching = toml::to_string(&link_matching)?;
+        // The setting table only exists in up to date DBs
+        if version != Self::SCHEMA_VERSION
+            || Self::get_setting(&db, "link_matching")?.as_ref() != Some(&matching)
+        {
+            // The DB is only a cache of the notes dir, so it is simply rebuilt
+            info!("Rebuilding outdated database: {:?}", path);
+            drop(db);
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+            db = Connection::open(path)?;
+            db.pragma_update(None, "user_version", Self::SCHEMA_VERSION)?;
+            db.execute_batch(include_str!("db.sql"))?;
+            let sql = "INSERT INTO setting (key, value) VALUES ('link_matching', ?)";
+            db.execute(sql, params![matching])?;
+        }
         Ok(Self { db })
     }
 
@@ -23,20 +70,40 @@ impl SovDb {
         Ok(())
     }
 
+    fn get_setting(db: &Connection, key: &str) -> Result<Option<String>> {
+        let sql = "SELECT value FROM setting WHERE key = ?";
+        let value = db.query_row(sql, params![key], |r| r.get(0)).optional()?;
+        Ok(value)
+    }
+
     pub fn insert_notes(&mut self, notes: &[SovNote]) -> Result<()> {
         let tx = self.db.transaction()?;
         {
             // Preparing statements outside of the loop is more efficient
-            let mut ins_note =
-                tx.prepare("INSERT INTO note (filename, path) VALUES (?, ?) RETURNING(note_id)")?;
+            let mut ins_note = tx.prepare(
+                "INSERT INTO note (filename, path, mtime, size, hash, frontmatter_error, word_count) VALUES (?, ?, ?, ?, ?, ?, ?) RETURNING(note_id)",
+            )?;
+            let mut upd_note =
+                tx.prepare("UPDATE note SET mtime = ?, size = ?, hash = ?, frontmatter_error = ?, word_count = ? WHERE note_id = ?")?;
             let mut ins_alias =
                 tx.prepare("INSERT INTO alias (alias_id, note_id) VALUES (?, ?)")?;
             let mut ins_tag = tx.prepare("INSERT INTO tag (name) VALUES (?) RETURNING(tag_id)")?;
-            let mut ins_tag_note =
-                tx.prepare("INSERT INTO tag_note (tag_id, note_id) VALUES (?, ?)")?;
+            let mut ins_tag_note = tx.prepare(
+                "INSERT INTO tag_note (tag_id, note_id, start, end) VALUES (?, ?, ?, ?)",
+            )?;
             let mut ins_link = tx.prepare(
-                "INSERT INTO link (src_note, link_value, alias, header, start, end) VALUES (?, ?, ?, ?, ?, ?)",
+                "INSERT INTO link (src_note, kind, link_value, alias, header, block, path, start, end) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )?;
+            let mut ins_heading = tx.prepare(
+                "INSERT INTO heading (note_id, level, text, slug, start_line, end_line) VALUES (?, ?, ?, ?, ?, ?)",
+            )?;
+            let mut ins_property =
+                tx.prepare("INSERT INTO property (note_id, key, value, kind) VALUES (?, ?, ?, ?)")?;
+            let mut ins_block = tx.prepare(
+                "INSERT OR IGNORE INTO block (note_id, block_id, line) VALUES (?, ?, ?)",
             )?;
+            let mut ins_fts = tx
+                .prepare("INSERT INTO note_fts (title, aliases, body, note_id) VALUES (?, ?, ?, ?)")?;
 
             for note in notes {
                 let path = note
@@ -47,10 +114,28 @@ impl SovDb {
                 let sql = "SELECT note_id FROM note WHERE path = ?";
                 let p = params![path];
                 let id: Option<u64> = tx.query_row(sql, p, |r| r.get(0)).optional()?;
+                let stat = &note.stat;
                 let id = if let Some(id) = id {
+                    let p = params![
+                        stat.mtime,
+                        stat.size,
+                        stat.hash,
+                        note.frontmatter_error,
+                        note.word_count,
+                        id
+                    ];
+                    upd_note.execute(p)?;
                     id
                 } else {
-                    let p = params![note.filename, path,];
+                    let p = params![
+                        note.filename,
+                        path,
+                        stat.mtime,
+                        stat.size,
+                        stat.hash,
+                        note.frontmatter_error,
+                        note.word_count
+                    ];
                     let id: u64 = ins_note.query_row(p, |r| r.get(0))?;
                     id
                 };
@@ -68,6 +153,22 @@ impl SovDb {
                 let p = params![id];
                 tx.execute(sql, p)?;
 
+                let sql = "DELETE FROM heading WHERE note_id = ?";
+                let p = params![id];
+                tx.execute(sql, p)?;
+
+                let sql = "DELETE FROM block WHERE note_id = ?";
+                let p = params![id];
+                tx.execute(sql, p)?;
+
+                let sql = "DELETE FROM property WHERE note_id = ?";
+                let p = params![id];
+                tx.execute(sql, p)?;
+
+                let sql = "DELETE FROM note_fts WHERE note_id = ?";
+                let p = params![id];
+                tx.execute(sql, p)?;
+
                 // insert new metadata
                 if let Some(aliases) = &note.yaml.aliases {
                     for alias in aliases {
@@ -76,7 +177,13 @@ impl SovDb {
                     }
                 }
 
-                for tag_name in &note.yaml.tags {
+                // YAML tags have no position
+                let yaml_tags = note.yaml.tags.iter().map(|t| (t, None, None));
+                let inline_tags = note
+                    .inline_tags
+                    .iter()
+                    .map(|t| (&t.name, Some(t.start), Some(t.end)));
+                for (tag_name, start, end) in yaml_tags.chain(inline_tags) {
                     let sql = "SELECT tag_id FROM tag WHERE name = ?";
                     let p = params![tag_name];
                     let tag_id: Option<u64> = tx.query_row(sql, p, |r| r.get(0)).optional()?;
@@ -87,14 +194,43 @@ impl SovDb {
                         let id: u64 = ins_tag.query_row(p, |r| r.get(0))?;
                         id
                     };
-                    let p = params![tag_id, id];
+                    let p = params![tag_id, id, start, end];
                     ins_tag_note.execute(p)?;
                 }
 
                 for link in &note.links {
-                    let p = params![id, link.value, link.alias, link.header, link.start, link.end,];
+                    let p = params![
+                        id,
+                        link.kind,
+                        link.value,
+                        link.alias,
+                        link.header,
+                        link.block,
+                        link.path,
+                        link.start,
+                        link.end,
+                    ];
                     ins_link.execute(p)?;
                 }
+
+                for h in &note.headings {
+                    let p = params![id, h.level, h.text, h.slug, h.start, h.end];
+                    ins_heading.execute(p)?;
+                }
+
+                for prop in &note.properties {
+                    let p = params![id, prop.key, prop.value, prop.kind.as_str()];
+                    ins_property.execute(p)?;
+                }
+
+                for block in &note.blocks {
+                    let p = params![id, block.id, block.line];
+                    ins_block.execute(p)?;
+                }
+
+                let aliases = note.yaml.aliases.as_deref().unwrap_or_default().join(" ");
+                let p = params![note.filename, aliases, note.content, id];
+                ins_fts.execute(p)?;
             }
         }
         tx.commit()?;
@@ -102,26 +238,186 @@ impl SovDb {
         Ok(())
     }
 
-    pub fn get_unique_tags(&self) -> Result<Vec<String>> {
-        let mut stmt = self.db.prepare("SELECT name FROM tag")?;
-        let rows = stmt.query_map([], |row| row.get(0))?;
-        let mut tags = Vec::new();
-        for row in rows {
-            tags.push(row?);
+    /// Lists the tags with the notes using them, sorted by name
+    pub fn get_tag_usages(&self) -> Result<Vec<TagUsage>> {
+        let mut stmt = self.db.prepare(
+            "SELECT DISTINCT t.name, n.path, n.mtime FROM tag t
+            JOIN tag_note tn USING(tag_id)
+            JOIN note n USING(note_id)
+            ORDER BY t.name, n.path",
+        )?;
+        let mut rows = stmt.query([])?;
+        let mut tags: Vec<TagUsage> = Vec::new();
+        while let Some(row) = rows.next()? {
+            let tag: String = row.get(0)?;
+            let path: String = row.get(1)?;
+            let mtime: i64 = row.get(2)?;
+            if tags.last().is_none_or(|t| t.tag != tag) {
+                tags.push(TagUsage::new(tag));
+            }
+            if let Some(usage) = tags.last_mut() {
+                usage.add_note(PathBuf::from(path), mtime);
+            }
         }
         Ok(tags)
     }
 
-    pub fn get_note_by_filename(&self, filename: &str) -> Result<Option<PathBuf>> {
-        let mut stmt = self
+    /// Lists the distinct `(tag, note_id)` pairs
+    pub fn get_tag_notes(&self) -> Result<Vec<(String, u64)>> {
+        let mut stmt = self.db.prepare(
+            "SELECT DISTINCT t.name, tn.note_id FROM tag t JOIN tag_note tn USING(tag_id)",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        let mut tag_notes = Vec::new();
+        for row in rows {
+            tag_notes.push(row?);
+        }
+        Ok(tag_notes)
+    }
+
+    /// Lists the `(note_id, path)` of all notes
+    pub fn get_all_notes(&self) -> Result<Vec<(u64, PathBuf)>> {
+        let mut stmt = self.db.prepare("SELECT note_id, path FROM note")?;
+        let rows = stmt.query_map([], |row| {
+            let path: String = row.get(1)?;
+            Ok((row.get(0)?, PathBuf::from(path)))
+        })?;
+        let mut notes = Vec::new();
+        for row in rows {
+            notes.push(row?);
+        }
+        Ok(notes)
+    }
+
+    /// Lists the `(alias, note_id)` of all notes
+    pub fn get_all_aliases(&self) -> Result<Vec<(String, u64)>> {
+        let mut stmt = self.db.prepare("SELECT alias_id, note_id FROM alias")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        let mut aliases = Vec::new();
+        for row in rows {
+            aliases.push(row?);
+        }
+        Ok(aliases)
+    }
+
+    /// Resolves the target note of every link, `resolve` is given the path of
+    /// the note containing the link, the link kind, value and path and returns
+    /// the target note, if any, and the number of matching notes
+    pub fn update_link_targets(
+        &mut self,
+        is_affected: impl Fn(&Path, &str) -> bool,
+        resolve: impl Fn(&Path, LinkKind, &str, Option<&str>) -> (Option<u64>, usize),
+    ) -> Result<()> {
+        let tx = self.db.transaction()?;
+        {
+            let mut select = tx.prepare(
+                "SELECT l.link_id, n.path, l.kind, l.link_value, l.path, l.target_note, l.targets
+                FROM link l JOIN note n ON n.note_id = l.src_note",
+            )?;
+            let mut update =
+                tx.prepare("UPDATE link SET target_note = ?, targets = ? WHERE link_id = ?")?;
+            let mut rows = select.query([])?;
+            while let Some(row) = rows.next()? {
+                let link_id: u64 = row.get(0)?;
+                let src_path: String = row.get(1)?;
+                let kind: LinkKind = row.get(2)?;
+                let value: String = row.get(3)?;
+                if !is_affected(Path::new(&src_path), &value) {
+                    continue;
+                }
+                let path: Option<String> = row.get(4)?;
+                let old_target: (Option<u64>, usize) = (row.get(5)?, row.get(6)?);
+                let target = resolve(Path::new(&src_path), kind, &value, path.as_deref());
+                if target != old_target {
+                    update.execute(params![target.0, target.1, link_id])?;
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Lists the notes whose frontmatter could not be parsed, sorted by path
+    pub fn get_frontmatter_errors(&self) -> Result<Vec<NoteError>> {
+        let mut stmt = self.db.prepare(
+            "SELECT path, frontmatter_error FROM note
+            WHERE frontmatter_error IS NOT NULL ORDER BY path",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let path: String = row.get(0)?;
+            Ok(NoteError {
+                path: PathBuf::from(path),
+                error: row.get(1)?,
+            })
+        })?;
+        let mut errors = Vec::new();
+        for row in rows {
+            errors.push(row?);
+        }
+        Ok(errors)
+    }
+
+    /// Lists the notes sharing their filename with another note, sorted by
+    /// filename and path
+    pub fn get_duplicate_notes(&self) -> Result<Vec<(String, PathBuf)>> {
+        let mut stmt = self.db.prepare(
+            "SELECT filename, path FROM note WHERE filename IN (
+                SELECT filename FROM note GROUP BY filename HAVING COUNT(*) > 1
+            ) ORDER BY filename, path",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let path: String = row.get(1)?;
+            Ok((row.get(0)?, PathBuf::from(path)))
+        })?;
+        let mut notes = Vec::new();
+        for row in rows {
+            notes.push(row?);
+        }
+        Ok(notes)
+    }
+
+    /// Lists the notes sharing the filename of a note, the note included,
+    /// sorted by path
+    pub fn get_namesakes(&self, note_id: u64) -> Result<Vec<(String, PathBuf)>> {
+        let mut stmt = self.db.prepare(
+            "SELECT n.filename, n.path FROM note n
+            JOIN note d ON d.filename = n.filename
+            WHERE d.note_id = ?1 ORDER BY n.path",
+        )?;
+        let rows = stmt.query_map(params![note_id], |row| {
+            let path: String = row.get(1)?;
+            Ok((row.get(0)?, PathBuf::from(path)))
+        })?;
+        let mut notes = Vec::new();
+        for row in rows {
+            notes.push(row?);
+        }
+        Ok(notes)
+    }
+
+    pub fn get_frontmatter_error(&self, note_id: u64) -> Result<Option<String>> {
+        let sql = "SELECT frontmatter_error FROM note WHERE note_id = ?1";
+        let error = self
             .db
-            .prepare("SELECT path FROM note WHERE filename = ?")?;
-        let p = params![filename];
-        // TODO: handle multiple rows
-        let path = stmt
-            .query_row(p, |r| r.get(0).map(|p: String| PathBuf::from(p)))
+            .query_row(sql, params![note_id], |r| r.get(0))
             .optional()?;
-        Ok(path)
+        Ok(error.flatten())
+    }
+
+    /// Whether a note has no word outside of its frontmatter
+    pub fn is_empty_note(&self, note_id: u64) -> Result<bool> {
+        let sql = "SELECT word_count = 0 FROM note WHERE note_id = ?1";
+        let empty = self
+            .db
+            .query_row(sql, params![note_id], |r| r.get(0))
+            .optional()?;
+        Ok(empty.unwrap_or(false))
+    }
+
+    /// Whether no note links to a note
+    pub fn is_orphaned_note(&self, note_id: u64) -> Result<bool> {
+        let sql = "SELECT NOT EXISTS (SELECT 1 FROM link WHERE target_note = ?1)";
+        Ok(self.db.query_row(sql, params![note_id], |r| r.get(0))?)
     }
 
     pub fn get_all_note_paths(&self) -> Result<HashSet<PathBuf>> {
@@ -135,6 +431,32 @@ impl SovDb {
         Ok(paths)
     }
 
+    pub fn get_all_note_stats(&self) -> Result<HashMap<PathBuf, NoteStat>> {
+        let mut stmt = self.db.prepare("SELECT path, mtime, size, hash FROM note")?;
+        let mut rows = stmt.query([])?;
+        let mut stats = HashMap::new();
+        while let Some(row) = rows.next()? {
+            let path: String = row.get(0)?;
+            let stat = NoteStat {
+                mtime: row.get(1)?,
+                size: row.get(2)?,
+                hash: row.get(3)?,
+            };
+            stats.insert(PathBuf::from(path), stat);
+        }
+        Ok(stats)
+    }
+
+    pub fn update_note_stat(&self, path: &Path, stat: &NoteStat) -> Result<()> {
+        let path = path
+            .to_str()
+            .ok_or(SovError::InvalidPath(path.to_path_buf()))?;
+        let sql = "UPDATE note SET mtime = ?, size = ?, hash = ? WHERE path = ?";
+        let p = params![stat.mtime, stat.size, stat.hash, path];
+        self.db.execute(sql, p)?;
+        Ok(())
+    }
+
     pub fn get_all_note_names(&self) -> Result<Vec<String>> {
         let mut stmt = self.db.prepare("SELECT filename FROM note")?;
         let rows = stmt.query_map([], |row| row.get(0))?;
@@ -162,7 +484,9 @@ impl SovDb {
     }
 
     pub fn get_all_orphaned_notes(&self) -> Result<Vec<PathBuf>> {
-        let sql = "SELECT path FROM note WHERE filename NOT IN (SELECT link_value FROM link)";
+        let sql = "SELECT path FROM note WHERE note_id NOT IN (
+            SELECT target_note FROM link WHERE target_note IS NOT NULL
+        )";
         let mut stmt = self.db.prepare(sql)?;
         let rows = stmt.query_map([], |row| row.get(0))?;
         let mut paths = Vec::new();
@@ -173,114 +497,198 @@ impl SovDb {
         Ok(paths)
     }
 
-    pub fn get_all_dead_links(&self) -> Result<Vec<(PathBuf, String)>> {
-        let sql = "
-            SELECT path, link_value FROM note t1
-            JOIN link t2 ON t1.note_id = t2.src_note
-            WHERE link_value NOT IN (
-                SELECT filename FROM note
-            )";
-        let mut stmt = self.db.prepare(sql)?;
+    pub fn get_all_dead_links(&self) -> Result<Vec<(PathBuf, Link)>> {
+        self.get_all_links_where(Self::DEAD_LINK_COND)
+    }
+
+    /// Lists the links matching several notes
+    pub fn get_all_ambiguous_links(&self) -> Result<Vec<(PathBuf, Link)>> {
+        self.get_all_links_where("l.targets > 1")
+    }
+
+    /// Lists the links matching a single note
+    pub fn get_all_resolved_links(&self) -> Result<Vec<(PathBuf, Link)>> {
+        self.get_all_links_where("l.target_note IS NOT NULL")
+    }
+
+    /// Lists the `(src_note, target_note, link_value)` of the links between
+    /// notes, `target_note` is `None` for links to a missing note. Ambiguous
+    /// links and image embeds are left out.
+    /// The `(src_note, target_note, kind, link_value, path)` of the resolved
+    /// links and of the dead links to missing notes
+    #[allow(clippy::type_complexity)]
+    pub fn get_link_graph(
+        &self,
+    ) -> Result<Vec<(u64, Option<u64>, LinkKind, String, Option<String>)>> {
+        let mut stmt = self.db.prepare(
+            "
+            SELECT n.note_id, l.target_note, l.kind, l.link_value, l.path FROM note n
+            JOIN link l ON n.note_id = l.src_note
+            WHERE l.target_note IS NOT NULL
+            OR (l.targets = 0 AND l.kind != 'image_embed')",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+            ))
+        })?;
+        let mut links = Vec::new();
+        for row in rows {
+            links.push(row?);
+        }
+        Ok(links)
+    }
+
+    fn get_all_links_where(&self, cond: &str) -> Result<Vec<(PathBuf, Link)>> {
+        let sql = format!(
+            "
+            SELECT n.path, {} FROM note n
+            JOIN link l ON n.note_id = l.src_note
+            WHERE {}",
+            Self::LINK_COLUMNS,
+            cond
+        );
+        let mut stmt = self.db.prepare(&sql)?;
         let mut rows = stmt.query([])?;
-        let mut paths = Vec::new();
+        let mut links = Vec::new();
         while let Some(row) = rows.next()? {
             let path: String = row.get(0)?;
-            let dead_link = row.get(1)?;
-            paths.push((PathBuf::from(path), dead_link));
+            let link = Self::link_from_row(row, 1)?;
+            links.push((PathBuf::from(path), link));
         }
-        Ok(paths)
+        Ok(links)
     }
 
-    pub fn get_note_id_by_filename(&self, filename: &str) -> Result<Option<u64>> {
-        let sql = "SELECT note_id FROM note WHERE filename = ?";
-        let p = params![filename];
-        let id: Option<u64> = self.db.query_row(sql, p, |r| r.get(0)).optional()?;
-        Ok(id)
+    /// Lists the notes without any word outside of their frontmatter
+    pub fn get_empty_notes(&self) -> Result<Vec<PathBuf>> {
+        let mut stmt = self
+            .db
+            .prepare("SELECT path FROM note WHERE word_count = 0 ORDER BY path")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        let mut paths = Vec::new();
+        for row in rows {
+            let path: String = row?;
+            paths.push(PathBuf::from(path));
+        }
+        Ok(paths)
     }
 
-    pub fn get_backlinks(&self, link_value: &str) -> Result<Vec<(PathBuf, Link)>> {
-        let sql = "
-            SELECT n.path, l.link_value, l.alias, l.header, l.start, l.end FROM note n
+    pub fn get_backlinks(
+        &self,
+        note_id: u64,
+        kind: Option<LinkKind>,
+    ) -> Result<Vec<(PathBuf, Link)>> {
+        let sql = format!(
+            "
+            SELECT n.path, {} FROM note n
             JOIN link l ON n.note_id = l.src_note
-            WHERE link_value = ?";
-        let mut stmt = self.db.prepare(sql)?;
-        let p = params![link_value];
+            WHERE l.target_note = ?1 AND (?2 IS NULL OR l.kind = ?2)",
+            Self::LINK_COLUMNS
+        );
+        let mut stmt = self.db.prepare(&sql)?;
+        let p = params![note_id, kind];
         let mut rows = stmt.query(p)?;
         let mut backlinks = Vec::new();
         while let Some(row) = rows.next()? {
             let path: String = row.get(0)?;
-            let link_value: String = row.get(1)?;
-            let alias: Option<String> = row.get(2)?;
-            let header: Option<String> = row.get(3)?;
-            let start: usize = row.get(4)?;
-            let end: usize = row.get(5)?;
-            let link = Link {
-                value: link_value,
-                alias,
-                header,
-                start,
-                end,
-            };
+            let link = Self::link_from_row(row, 1)?;
             backlinks.push((PathBuf::from(path), link));
         }
         Ok(backlinks)
     }
 
-    pub fn get_links(&self, filename: &str) -> Result<Vec<Link>> {
-        let mut links = Vec::new();
-        let Some(note_id) = self.get_note_id_by_filename(filename)? else {
-            return Ok(links);
-        };
-        let sql = "SELECT link_value, alias, header, start, end FROM link WHERE src_note = ?";
-        let p = params![note_id];
-        let mut stmt = self.db.prepare(sql)?;
+    pub fn get_links(&self, note_id: u64, kind: Option<LinkKind>) -> Result<Vec<Link>> {
+        let cond = "?2 IS NULL OR l.kind = ?2";
+        self.get_note_links(cond, params![note_id, kind])
+    }
+
+    pub fn get_dead_links(&self, note_id: u64) -> Result<Vec<Link>> {
+        self.get_note_links(Self::DEAD_LINK_COND, params![note_id])
+    }
+
+    /// Lists the links of a note matching several notes
+    pub fn get_ambiguous_links(&self, note_id: u64) -> Result<Vec<Link>> {
+        self.get_note_links("l.targets > 1", params![note_id])
+    }
+
+    /// Lists the links of a note matching `cond`, `?1` being the note id
+    fn get_note_links(&self, cond: &str, p: impl Params) -> Result<Vec<Link>> {
+        let sql = format!(
+            "SELECT {} FROM link l WHERE l.src_note = ?1 AND ({})",
+            Self::LINK_COLUMNS,
+            cond
+        );
+        let mut stmt = self.db.prepare(&sql)?;
         let mut rows = stmt.query(p)?;
+        let mut links = Vec::new();
         while let Some(row) = rows.next()? {
-            let link_value = row.get(0)?;
-            let alias: Option<String> = row.get(1)?;
-            let header: Option<String> = row.get(2)?;
-            let start = row.get(3)?;
-            let end = row.get(4)?;
-            links.push(Link {
-                value: link_value,
-                alias,
-                header,
-                start,
-                end,
-            });
+            links.push(Self::link_from_row(row, 0)?);
         }
         Ok(links)
     }
 
-    pub fn get_dead_links(&self, filename: &str) -> Result<Vec<Link>> {
-        let mut links = Vec::new();
-        let Some(note_id) = self.get_note_id_by_filename(filename)? else {
-            return Ok(links);
-        };
+    /// Reads the [`Self::LINK_COLUMNS`] of a row, starting at column `offset`
+    fn link_from_row(row: &Row, offset: usize) -> Result<Link> {
+        Ok(Link {
+            kind: row.get(offset)?,
+            value: row.get(offset + 1)?,
+            alias: row.get(offset + 2)?,
+            header: row.get(offset + 3)?,
+            block: row.get(offset + 4)?,
+            path: row.get(offset + 5)?,
+            start: row.get(offset + 6)?,
+            end: row.get(offset + 7)?,
+        })
+    }
+
+    pub fn get_block(&self, note_path: &Path, block_id: &str) -> Result<Option<Block>> {
+        let note_path = note_path
+            .to_str()
+            .ok_or(SovError::InvalidPath(note_path.to_path_buf()))?;
         let sql = "
-            SELECT link_value, alias, header, start, end FROM link
-            WHERE src_note = ? AND link_value NOT IN (
-                SELECT filename FROM note
-            )";
+            SELECT b.block_id, b.line FROM block b
+            JOIN note n USING(note_id)
+            WHERE n.path = ? AND b.block_id = ?";
+        let p = params![note_path, block_id];
+        let block = self
+            .db
+            .query_row(sql, p, |r| {
+                Ok(Block {
+                    id: r.get(0)?,
+                    line: r.get(1)?,
+                })
+            })
+            .optional()?;
+        Ok(block)
+    }
 
-        let p = params![note_id];
-        let mut stmt = self.db.prepare(sql)?;
-        let mut rows = stmt.query(p)?;
-        while let Some(row) = rows.next()? {
-            let link_value = row.get(0)?;
-            let alias: Option<String> = row.get(1)?;
-            let header: Option<String> = row.get(2)?;
-            let start = row.get(3)?;
-            let end = row.get(4)?;
-            links.push(Link {
-                value: link_value,
-                alias,
-                header,
-                start,
-                end,
-            });
-        }
-        Ok(links)
+    pub fn get_heading(&self, note_path: &Path, header: &str) -> Result<Option<Heading>> {
+        let note_path = note_path
+            .to_str()
+            .ok_or(SovError::InvalidPath(note_path.to_path_buf()))?;
+        let sql = "
+            SELECT level, text, slug, start_line, end_line FROM heading h
+            JOIN note n USING(note_id)
+            WHERE n.path = ?1 AND (h.text = ?2 COLLATE NOCASE OR h.slug = ?2)
+            ORDER BY start_line";
+        let p = params![note_path, header];
+        let heading = self
+            .db
+            .query_row(sql, p, |r| {
+                Ok(Heading {
+                    level: r.get(0)?,
+                    text: r.get(1)?,
+                    slug: r.get(2)?,
+                    start: r.get(3)?,
+                    end: r.get(4)?,
+                })
+            })
+            .optional()?;
+        Ok(heading)
     }
 
     pub fn delete_note_by_path(&self, path: &Path) -> Result<()> {
@@ -293,18 +701,89 @@ impl SovDb {
         Ok(())
     }
 
+    pub fn delete_notes_in_dir(&self, dir: &Path) -> Result<()> {
+        let dir = dir
+            .to_str()
+            .ok_or(SovError::InvalidPath(dir.to_path_buf()))?;
+        let prefix = format!("{}/", dir.trim_end_matches('/'));
+        let sql = "DELETE FROM note WHERE substr(path, 1, length(?1)) = ?1";
+        let p = params![prefix];
+        self.db.execute(sql, p)?;
+        Ok(())
+    }
+
     pub fn clean_dead_tags(&self) -> Result<()> {
         let sql = "DELETE FROM tag WHERE tag_id NOT IN (SELECT tag_id FROM tag_note)";
         self.db.execute(sql, [])?;
         Ok(())
     }
 
+    /// Lists the notes having the property `key`, with the given value if any
+    pub fn find_notes_by_property(&self, key: &str, value: Option<&str>) -> Result<Vec<PathBuf>> {
+        let mut stmt = self.db.prepare(
+            "SELECT DISTINCT n.path FROM property p
+            JOIN note n USING(note_id)
+            WHERE p.key = ?1 AND (?2 IS NULL OR p.value = ?2)",
+        )?;
+        let p = params![key, value];
+        let rows = stmt.query_map(p, |row| row.get(0))?;
+        let mut paths = Vec::new();
+        for row in rows {
+            let path: String = row?;
+            paths.push(PathBuf::from(path));
+        }
+        Ok(paths)
+    }
+
+    /// Finds the notes matching a compiled [`crate::query::Query`]
+    pub fn find_notes_by_query(
+        &self,
+        query: &QuerySql,
+        sort: QuerySort,
+        limit: Option<usize>,
+    ) -> Result<Vec<PathBuf>> {
+        let mut sql = format!(
+            "SELECT n.path FROM note n WHERE {} ORDER BY {}",
+            query.cond,
+            sort.as_sql()
+        );
+        if let Some(limit) = limit {
+            sql.push_str(&format!(" LIMIT {}", limit));
+        }
+        let mut stmt = self.db.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(&query.params), |row| row.get(0))?;
+        let mut paths = Vec::new();
+        for row in rows {
+            let path: String = row?;
+            paths.push(PathBuf::from(path));
+        }
+        Ok(paths)
+    }
+
+    /// Lists the property keys with the number of notes using them
+    pub fn get_property_keys(&self) -> Result<Vec<PropertyCount>> {
+        let mut stmt = self.db.prepare(
+            "SELECT key, COUNT(DISTINCT note_id) FROM property GROUP BY key ORDER BY key",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(PropertyCount {
+                key: row.get(0)?,
+                count: row.get(1)?,
+            })
+        })?;
+        let mut keys = Vec::new();
+        for row in rows {
+            keys.push(row?);
+        }
+        Ok(keys)
+    }
+
     pub fn find_notes_by_tag(&self, tag: &str) -> Result<Vec<PathBuf>> {
         let mut stmt = self.db.prepare(
-            "SELECT n.path FROM tag t
+            "SELECT DISTINCT n.path FROM tag t
             JOIN tag_note tn USING(tag_id)
             JOIN note n USING(note_id)
-            WHERE t.name = ?",
+            WHERE t.name = ?1 OR substr(t.name, 1, length(?1) + 1) = ?1 || '/'",
         )?;
         let p = params![tag];
         let rows = stmt.query_map(p, |row| row.get(0))?;
@@ -315,4 +794,52 @@ impl SovDb {
         }
         Ok(paths)
     }
+
+    pub fn search_text(&self, query: &str) -> Result<Vec<TextMatch>> {
+        // \x01 and \x02 mark the matched terms in the highlighted body, they are
+        // only used to locate the line of the first match
+        let sql = "
+            SELECT n.path,
+                snippet(note_fts, 2, '**', '**', '...', 16),
+                highlight(note_fts, 2, char(1), char(2))
+            FROM note_fts f
+            JOIN note n ON n.note_id = f.note_id
+            WHERE note_fts MATCH ?
+            ORDER BY rank";
+        let mut stmt = self.db.prepare(sql)?;
+        let p = params![query];
+        let mut rows = stmt.query(p)?;
+        let mut matches = Vec::new();
+        while let Some(row) = rows.next()? {
+            let path: String = row.get(0)?;
+            let snippet: String = row.get(1)?;
+            let highlighted: String = row.get(2)?;
+            let line = match highlighted.find('\x01') {
+                Some(off) => highlighted[..off].matches('\n').count() + 1,
+                // the match is in the title or the aliases
+                None => 1,
+            };
+            matches.push(TextMatch {
+                path: PathBuf::from(path),
+                line,
+                snippet: snippet.replace('\n', " "),
+            });
+        }
+        Ok(matches)
+    }
+}
+
+impl ToSql for LinkKind {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(self.as_str().into())
+    }
+}
+
+impl FromSql for LinkKind {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value
+            .as_str()?
+            .parse()
+            .map_err(|e| FromSqlError::Other(Box::new(e)))
+    }
 }
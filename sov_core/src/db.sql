@@ -5,7 +5,15 @@
 CREATE TABLE IF NOT EXISTS note (
     note_id INTEGER PRIMARY KEY AUTOINCREMENT,
     filename TEXT NOT NULL,
-    path TEXT NOT NULL
+    path TEXT NOT NULL,
+    -- modification time in nanoseconds since the epoch
+    mtime INTEGER NOT NULL,
+    size INTEGER NOT NULL,
+    hash TEXT NOT NULL,
+    -- NULL if the frontmatter is valid
+    frontmatter_error TEXT,
+    -- number of words outside of the frontmatter
+    word_count INTEGER NOT NULL
 );
 
 ----------------------------------------
@@ -15,11 +23,45 @@ CREATE TABLE IF NOT EXISTS note (
 CREATE TABLE IF NOT EXISTS link (
     link_id INTEGER PRIMARY KEY AUTOINCREMENT,
     src_note TEXT NOT NULL REFERENCES note(note_id),
+    -- link, embed, image_embed or markdown
+    kind TEXT NOT NULL,
     link_value TEXT NOT NULL,
     alias TEXT,
     header TEXT,
+    block TEXT,
+    -- relative path of markdown links
+    path TEXT,
     start INTEGER NOT NULL,
-    end INTEGER NOT NULL
+    end INTEGER NOT NULL,
+    -- resolved note, NULL if missing or ambiguous
+    target_note INTEGER REFERENCES note(note_id),
+    -- number of notes matching the link, more than 1 if ambiguous
+    targets INTEGER NOT NULL DEFAULT 0
+);
+
+----------------------------------------
+-- heading
+----------------------------------------
+
+CREATE TABLE IF NOT EXISTS heading (
+    heading_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    note_id INTEGER NOT NULL REFERENCES note(note_id),
+    level INTEGER NOT NULL,
+    text TEXT NOT NULL,
+    slug TEXT NOT NULL,
+    start_line INTEGER NOT NULL,
+    end_line INTEGER NOT NULL
+);
+
+----------------------------------------
+-- block
+----------------------------------------
+
+CREATE TABLE IF NOT EXISTS block (
+    note_id INTEGER NOT NULL REFERENCES note(note_id),
+    block_id TEXT NOT NULL,
+    line INTEGER NOT NULL,
+    PRIMARY KEY(note_id, block_id)
 );
 
 ----------------------------------------
@@ -34,7 +76,9 @@ CREATE TABLE IF NOT EXISTS tag (
 CREATE TABLE IF NOT EXISTS tag_note (
     tag_id INTEGER NOT NULL REFERENCES tag(tag_id),
     note_id INTEGER NOT NULL REFERENCES note(note_id),
-    PRIMARY KEY(tag_id, note_id)
+    -- char offsets of inline tags, NULL for YAML tags
+    start INTEGER,
+    end INTEGER
 );
 
 ----------------------------------------
@@ -48,6 +92,41 @@ CREATE TABLE IF NOT EXISTS alias (
 );
 
 
+----------------------------------------
+-- property
+----------------------------------------
+
+CREATE TABLE IF NOT EXISTS property (
+    note_id INTEGER NOT NULL REFERENCES note(note_id),
+    key TEXT NOT NULL,
+    value TEXT,
+    -- string, number, bool, date or null
+    kind TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS property_key ON property(key);
+
+----------------------------------------
+-- note_fts
+----------------------------------------
+
+CREATE VIRTUAL TABLE IF NOT EXISTS note_fts USING fts5(
+    title,
+    aliases,
+    body,
+    note_id UNINDEXED
+);
+
+----------------------------------------
+-- setting
+----------------------------------------
+
+-- config the index depends on, the DB is rebuilt when it changes
+CREATE TABLE IF NOT EXISTS setting (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+
 ----------------------------------------
 -- TRIGGERS
 ----------------------------------------
@@ -55,6 +134,10 @@ CREATE TABLE IF NOT EXISTS alias (
 CREATE TRIGGER IF NOT EXISTS remove_dead_note_metadata BEFORE DELETE ON note
 BEGIN
     DELETE FROM link WHERE src_note = OLD.note_id;
+    DELETE FROM heading WHERE note_id = OLD.note_id;
+    DELETE FROM block WHERE note_id = OLD.note_id;
     DELETE FROM tag_note WHERE note_id = OLD.note_id;
     DELETE FROM alias WHERE note_id = OLD.note_id;
+    DELETE FROM property WHERE note_id = OLD.note_id;
+    DELETE FROM note_fts WHERE note_id = OLD.note_id;
 END;
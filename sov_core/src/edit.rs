@@ -0,0 +1,181 @@
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::error::{Result, SovError};
+
+/// A rewrite of a note made by a vault-wide change, e.g. a tag rename, or a
+/// new note
+#[derive(Debug, Serialize)]
+pub struct NoteEdit {
+    pub path: PathBuf,
+    /// New content of the note
+    #[serde(skip)]
+    pub content: String,
+    /// Unified diff of the change, without context lines
+    pub diff: String,
+    /// Whether the note is created, an existing file is never overwritten
+    #[serde(skip)]
+    pub create: bool,
+}
+
+impl NoteEdit {
+    pub fn new(path: PathBuf, old: &str, new: String) -> Self {
+        let diff = Self::diff(&path, old, &new);
+        Self {
+            path,
+            content: new,
+            diff,
+            create: false,
+        }
+    }
+
+    /// A new note, see [`NoteEdit::write`]
+    pub fn create(path: PathBuf, content: String) -> Self {
+        Self {
+            create: true,
+            ..Self::new(path, "", content)
+        }
+    }
+
+    /// Writes the new content, creating the directory of new notes. Fails if a
+    /// new note already exists.
+    pub fn write(&self) -> Result<()> {
+        if !self.create {
+            std::fs::write(&self.path, &self.content)?;
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&self.path);
+        let mut file = match file {
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                return Err(SovError::NoteExists(self.path.clone()))
+            }
+            file => file?,
+        };
+        file.write_all(self.content.as_bytes())?;
+        Ok(())
+    }
+
+    fn diff(path: &Path, old: &str, new: &str) -> String {
+        let old: Vec<&str> = old.lines().collect();
+        let new: Vec<&str> = new.lines().collect();
+
+        // Only diff what is between the common prefix and suffix, edits are
+        // usually small
+        let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+        let suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(new[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let old_mid = &old[prefix..old.len() - suffix];
+        let new_mid = &new[prefix..new.len() - suffix];
+
+        // Longest common subsequence lengths of the suffixes
+        let mut lcs = vec![vec![0usize; new_mid.len() + 1]; old_mid.len() + 1];
+        for i in (0..old_mid.len()).rev() {
+            for j in (0..new_mid.len()).rev() {
+                lcs[i][j] = match old_mid[i] == new_mid[j] {
+                    true => lcs[i + 1][j + 1] + 1,
+                    false => lcs[i + 1][j].max(lcs[i][j + 1]),
+                };
+            }
+        }
+
+        let mut diff = format!("--- {0}\n+++ {0}\n", path.display());
+        let (mut i, mut j) = (0, 0);
+        while i < old_mid.len() || j < new_mid.len() {
+            if i < old_mid.len() && j < new_mid.len() && old_mid[i] == new_mid[j] {
+                i += 1;
+                j += 1;
+                continue;
+            }
+            // A hunk of consecutive changes
+            let (old_start, new_start) = (i, j);
+            let mut hunk = String::new();
+            while i < old_mid.len() || j < new_mid.len() {
+                if i < old_mid.len() && j < new_mid.len() && old_mid[i] == new_mid[j] {
+                    break;
+                }
+                if j == new_mid.len() || (i < old_mid.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+                    hunk.push_str(&format!("-{}\n", old_mid[i]));
+                    i += 1;
+                } else {
+                    hunk.push_str(&format!("+{}\n", new_mid[j]));
+                    j += 1;
+                }
+            }
+            diff.push_str(&format!(
+                "@@ -{} +{} @@\n{}",
+                Self::hunk_range(prefix + old_start, i - old_start),
+                Self::hunk_range(prefix + new_start, j - new_start),
+                hunk
+            ));
+        }
+        diff
+    }
+
+    /// `start,len` of a hunk, `start` being the 1-based first line, or the line
+    /// before the hunk for an empty range
+    fn hunk_range(start: usize, len: usize) -> String {
+        match len {
+            0 => format!("{},0", start),
+            _ => format!("{},{}", start + 1, len),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diff(old: &str, new: &str) -> String {
+        NoteEdit::new(PathBuf::from("n.md"), old, new.to_string()).diff
+    }
+
+    #[test]
+    fn diff_hunks() {
+        assert_eq!(
+            diff("a\nb\nc\n", "a\nB\nc\n"),
+            "--- n.md\n+++ n.md\n@@ -2,1 +2,1 @@\n-b\n+B\n"
+        );
+        assert_eq!(
+            diff("a\nb\nc\nd\ne\n", "a\nB\nc\nD\ne\n"),
+            "--- n.md\n+++ n.md\n@@ -2,1 +2,1 @@\n-b\n+B\n@@ -4,1 +4,1 @@\n-d\n+D\n"
+        );
+    }
+
+    #[test]
+    fn diff_empty_ranges() {
+        assert_eq!(
+            diff("a\nc\n", "a\nb\nc\n"),
+            "--- n.md\n+++ n.md\n@@ -1,0 +2,1 @@\n+b\n"
+        );
+        assert_eq!(
+            diff("a\nb\n", "b\n"),
+            "--- n.md\n+++ n.md\n@@ -1,1 +0,0 @@\n-a\n"
+        );
+        assert_eq!(diff("", "x\n"), "--- n.md\n+++ n.md\n@@ -0,0 +1,1 @@\n+x\n");
+        assert_eq!(diff("a\n", "a\n"), "--- n.md\n+++ n.md\n");
+    }
+
+    #[test]
+    fn create_does_not_overwrite() {
+        let dir = std::env::temp_dir().join(format!("sov-edit-{}", std::process::id()));
+        let path = dir.join("sub/n.md");
+        let edit = NoteEdit::create(path.clone(), "# n\n".to_string());
+        edit.write().unwrap();
+        assert!(matches!(edit.write(), Err(SovError::NoteExists(_))));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "# n\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
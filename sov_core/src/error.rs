@@ -21,6 +21,8 @@ pub enum SovError {
     TomlDe(#[from] toml::de::Error),
     #[error("toml serialize error: {0}")]
     TomlSer(#[from] toml::ser::Error),
+    #[error("watcher error: {0}")]
+    Notify(#[from] notify::Error),
 
     #[error("no config dir")]
     NoConfigDir,
@@ -32,16 +34,35 @@ pub enum SovError {
     // Invalid
     #[error("invalid link: {0}")]
     InvalidLink(String),
+    #[error("invalid link kind: {0}, expected link, embed, image_embed or markdown")]
+    InvalidLinkKind(String),
     #[error("invalid path: {0}")]
     InvalidPath(PathBuf),
-    #[error("file time error")]
-    InvalidTime,
+    #[error("invalid line: {0}")]
+    InvalidLine(usize),
+    #[error("invalid tag sort: {0}, expected name, count or modified")]
+    InvalidTagSort(String),
+    #[error("invalid query: {0}")]
+    InvalidQuery(String),
+    #[error("invalid saved query {0}: {1}")]
+    InvalidSavedQuery(String, Box<SovError>),
+    #[error("invalid fix: {0}, expected tags, link-case or ambiguous-links")]
+    InvalidFix(String),
+    #[error("no dead link to {0}")]
+    NoDeadLink(String),
     #[error("invalid notes dir: {0}")]
     InvalidNotesDir(PathBuf),
+    #[error("note already exists: {0}")]
+    NoteExists(PathBuf),
+
+    #[error("ambiguous note: {0} matches {1} notes, use a path-qualified name like `dir/{0}`")]
+    AmbiguousNote(String, usize),
 
     // Not Found
     #[error("note not found: {0}")]
     NoteNotFound(String),
+    #[error("saved query not found: {0}")]
+    QueryNotFound(String),
     #[error("script not found: {0}")]
     ScriptNotFound(String),
 }
@@ -0,0 +1,301 @@
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+/// The notes and the links between them, see [`crate::Sov::graph`]
+#[derive(Debug, Default, Serialize)]
+pub struct Graph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphNode {
+    /// Path of the note relative to the notes dir. For a missing note, the
+    /// path of markdown links or the link value as matched with filenames,
+    /// see [`crate::config::LinkMatching::key`]
+    pub id: String,
+    pub label: String,
+    /// `None` for a missing note
+    pub path: Option<PathBuf>,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphEdge {
+    pub source: String,
+    pub target: String,
+    /// Number of links from the source to the target
+    pub weight: usize,
+}
+
+/// Filters of [`crate::Sov::graph`], all notes without their dead links by
+/// default
+#[derive(Debug, Clone, Default)]
+pub struct GraphFilter {
+    /// Only notes with this tag or one of its nested tags
+    pub tag: Option<String>,
+    /// Only notes under this path, relative to the notes dir
+    pub path: Option<String>,
+    /// Only notes within `depth` links of this note, in either direction
+    pub root: Option<String>,
+    pub depth: usize,
+    /// Also include the missing notes that are linked to
+    pub dead_links: bool,
+}
+
+impl Graph {
+    /// Builds a graph from its nodes and its edges, each link being a `(source,
+    /// target)` pair of node ids. Links to unknown nodes are dropped and
+    /// repeated links are merged into a single weighted edge.
+    pub fn new(nodes: Vec<GraphNode>, links: Vec<(String, String)>) -> Self {
+        let ids: HashSet<&str> = nodes.iter().map(|n| n.id.as_str()).collect();
+        let mut weights: BTreeMap<(String, String), usize> = BTreeMap::new();
+        for (source, target) in links {
+            if ids.contains(source.as_str()) && ids.contains(target.as_str()) {
+                *weights.entry((source, target)).or_default() += 1;
+            }
+        }
+        let edges = weights
+            .into_iter()
+            .map(|((source, target), weight)| GraphEdge {
+                source,
+                target,
+                weight,
+            })
+            .collect();
+        let mut nodes = nodes;
+        nodes.sort_by(|a, b| a.id.cmp(&b.id));
+        Graph { nodes, edges }
+    }
+
+    /// Keeps the nodes within `depth` edges of `root`, following edges in
+    /// both directions
+    pub fn around(self, root: &str, depth: usize) -> Self {
+        let mut neighbours: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for edge in &self.edges {
+            neighbours
+                .entry(&edge.source)
+                .or_default()
+                .push(&edge.target);
+            neighbours
+                .entry(&edge.target)
+                .or_default()
+                .push(&edge.source);
+        }
+
+        let mut kept: HashSet<String> = HashSet::from([root.to_string()]);
+        let mut queue = VecDeque::from([(root, 0)]);
+        while let Some((id, distance)) = queue.pop_front() {
+            if distance == depth {
+                continue;
+            }
+            for next in neighbours.get(id).into_iter().flatten() {
+                if kept.insert(next.to_string()) {
+                    queue.push_back((next, distance + 1));
+                }
+            }
+        }
+
+        let Graph { nodes, edges } = self;
+        Graph {
+            nodes: nodes.into_iter().filter(|n| kept.contains(&n.id)).collect(),
+            edges: edges
+                .into_iter()
+                .filter(|e| kept.contains(&e.source) && kept.contains(&e.target))
+                .collect(),
+        }
+    }
+
+    /// Renders the graph in the Graphviz DOT language, missing notes are
+    /// dashed
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph sov {\n");
+        for node in &self.nodes {
+            let style = match node.path {
+                Some(_) => "",
+                None => ", style=dashed",
+            };
+            let _ = writeln!(
+                dot,
+                "    {} [label={}{}];",
+                dot_quote(&node.id),
+                dot_quote(&node.label),
+                style
+            );
+        }
+        for edge in &self.edges {
+            let _ = writeln!(
+                dot,
+                "    {} -> {} [weight={}];",
+                dot_quote(&edge.source),
+                dot_quote(&edge.target),
+                edge.weight
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Renders the graph in GraphML, e.g. for Gephi
+    pub fn to_graphml(&self) -> String {
+        let mut xml = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>\n",
+            "  <key id=\"path\" for=\"node\" attr.name=\"path\" attr.type=\"string\"/>\n",
+            "  <key id=\"tags\" for=\"node\" attr.name=\"tags\" attr.type=\"string\"/>\n",
+            "  <key id=\"missing\" for=\"node\" attr.name=\"missing\" attr.type=\"boolean\"/>\n",
+            "  <key id=\"weight\" for=\"edge\" attr.name=\"weight\" attr.type=\"int\"/>\n",
+            "  <graph id=\"sov\" edgedefault=\"directed\">\n",
+        ));
+        for node in &self.nodes {
+            let _ = writeln!(xml, "    <node id=\"{}\">", xml_escape(&node.id));
+            let _ = writeln!(
+                xml,
+                "      <data key=\"label\">{}</data>",
+                xml_escape(&node.label)
+            );
+            if let Some(path) = &node.path {
+                let _ = writeln!(
+                    xml,
+                    "      <data key=\"path\">{}</data>",
+                    xml_escape(&path.to_string_lossy())
+                );
+            }
+            if !node.tags.is_empty() {
+                let _ = writeln!(
+                    xml,
+                    "      <data key=\"tags\">{}</data>",
+                    xml_escape(&node.tags.join(","))
+                );
+            }
+            let _ = writeln!(
+                xml,
+                "      <data key=\"missing\">{}</data>",
+                node.path.is_none()
+            );
+            xml.push_str("    </node>\n");
+        }
+        for edge in &self.edges {
+            let _ = writeln!(
+                xml,
+                "    <edge source=\"{}\" target=\"{}\">",
+                xml_escape(&edge.source),
+                xml_escape(&edge.target)
+            );
+            let _ = writeln!(xml, "      <data key=\"weight\">{}</data>", edge.weight);
+            xml.push_str("    </edge>\n");
+        }
+        xml.push_str("  </graph>\n</graphml>\n");
+        xml
+    }
+}
+
+fn dot_quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str) -> GraphNode {
+        GraphNode {
+            id: id.into(),
+            label: id.into(),
+            path: Some(PathBuf::from(id)),
+            tags: Vec::new(),
+        }
+    }
+
+    fn link(source: &str, target: &str) -> (String, String) {
+        (source.into(), target.into())
+    }
+
+    fn ids(graph: &Graph) -> Vec<&str> {
+        graph.nodes.iter().map(|n| n.id.as_str()).collect()
+    }
+
+    /// a -> b -> c -> d, e -> a
+    fn chain() -> Graph {
+        let nodes = ["a", "b", "c", "d", "e"].map(node).to_vec();
+        let links = vec![
+            link("a", "b"),
+            link("b", "c"),
+            link("c", "d"),
+            link("e", "a"),
+        ];
+        Graph::new(nodes, links)
+    }
+
+    #[test]
+    fn edges_are_merged() {
+        let nodes = vec![node("b"), node("a")];
+        let links = vec![
+            link("a", "b"),
+            link("a", "b"),
+            link("b", "a"),
+            link("a", "x"),
+        ];
+        let graph = Graph::new(nodes, links);
+        assert_eq!(ids(&graph), vec!["a", "b"]);
+        let edges: Vec<_> = graph
+            .edges
+            .iter()
+            .map(|e| (e.source.as_str(), e.target.as_str(), e.weight))
+            .collect();
+        assert_eq!(edges, vec![("a", "b", 2), ("b", "a", 1)]);
+    }
+
+    #[test]
+    fn around_follows_both_directions() {
+        assert_eq!(ids(&chain().around("b", 0)), vec!["b"]);
+        assert_eq!(ids(&chain().around("b", 1)), vec!["a", "b", "c"]);
+        assert_eq!(ids(&chain().around("b", 2)), vec!["a", "b", "c", "d", "e"]);
+        let graph = chain().around("d", 1);
+        assert_eq!(ids(&graph), vec!["c", "d"]);
+        assert_eq!(graph.edges.len(), 1);
+    }
+
+    #[test]
+    fn dot_is_escaped() {
+        let mut missing = node(r#"a "b"\c"#);
+        missing.path = None;
+        let graph = Graph::new(
+            vec![node("n.md"), missing],
+            vec![link("n.md", r#"a "b"\c"#)],
+        );
+        assert_eq!(
+            graph.to_dot(),
+            concat!(
+                "digraph sov {\n",
+                "    \"a \\\"b\\\"\\\\c\" [label=\"a \\\"b\\\"\\\\c\", style=dashed];\n",
+                "    \"n.md\" [label=\"n.md\"];\n",
+                "    \"n.md\" -> \"a \\\"b\\\"\\\\c\" [weight=1];\n",
+                "}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn graphml_is_escaped() {
+        let mut n = node("<a & 'b'>.md");
+        n.tags = vec!["x".into(), "y\"z".into()];
+        let graph = Graph::new(vec![n], vec![]);
+        let xml = graph.to_graphml();
+        assert!(xml.contains("<node id=\"&lt;a &amp; &apos;b&apos;&gt;.md\">"));
+        assert!(xml.contains("<data key=\"tags\">x,y&quot;z</data>"));
+        assert!(xml.contains("<data key=\"missing\">false</data>"));
+    }
+}
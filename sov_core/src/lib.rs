@@ -1,57 +1,153 @@
 pub mod config;
 mod db;
+pub mod edit;
 pub mod error;
+pub mod graph;
+pub mod lint;
 pub mod note;
+pub mod query;
+mod resolve;
+pub mod tag;
+pub mod watcher;
 
-use std::collections::HashSet;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs::File;
 use std::io::BufReader;
 use std::os::unix::fs::MetadataExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
-use chrono::DateTime;
-use config::SovConfig;
+use config::{LinkMatching, SovConfig};
 use db::SovDb;
+use edit::NoteEdit;
 use error::{Result, SovError};
-use note::{Link, SovNote};
+use graph::{Graph, GraphFilter, GraphNode};
+use lint::{Fix, Lint, LintLevel, LintRule};
+use note::{
+    Block, DuplicateNotes, Heading, Link, LinkKind, Mention, NoteError, NoteStat, PropertyCount,
+    SovNote, TextMatch,
+};
+use query::{Query, QuerySort, QuerySql};
+use rayon::prelude::*;
+use resolve::{Resolver, Target};
 use ropey::Rope;
+use serde::Serialize;
+use tag::{TagNode, TagRename, TagSort, TagUsage};
 use tracing::info;
 use walkdir::WalkDir;
+use watcher::SovWatcher;
 
 pub struct Sov {
     config: SovConfig,
     db: SovDb,
+    /// By link matching, see [`Sov::resolver_with`]
+    resolvers: RefCell<HashMap<LinkMatching, Arc<Resolver>>>,
+    /// See [`Sov::mention_names`]
+    mention_names: RefCell<Option<Arc<MentionNames>>>,
+}
+
+/// Progress of [`Sov::index_with_progress`]
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct IndexProgress {
+    /// Number of notes parsed so far
+    pub parsed: usize,
+    /// Number of new or modified notes to parse
+    pub total: usize,
+}
+
+/// Notes that could not be fully indexed, an invalid note does not stop the
+/// indexing of the other notes
+#[derive(Debug, Default, Serialize)]
+pub struct IndexReport {
+    /// Unreadable notes are not indexed, notes with an invalid frontmatter
+    /// are indexed without their metadata
+    pub errors: Vec<NoteError>,
 }
 
 #[derive(Debug)]
 pub enum SovFeature {
     Index,
+    Watch,
     Daily,
     ListNotes,
-    ListTags,
+    ListTags {
+        tree: bool,
+        sort: TagSort,
+        min_count: usize,
+    },
     ListOrphans,
+    ListDuplicates,
     ListDeadLinks,
     ListAliases,
+    ListProperties,
     ListScripts,
     ResolveNote {
         note: String,
     },
     ResolveLinks {
         note: String,
+        kind: Option<LinkKind>,
     },
     ResolveDeadLinks {
         note: String,
     },
     ResolveBacklinks {
         note: String,
+        kind: Option<LinkKind>,
+    },
+    ResolveUnlinkedMentions {
+        note: String,
     },
     Rename {
         old_filename: String,
         new_filename: String,
     },
+    RenameTag {
+        old_tag: String,
+        new_tag: String,
+        dry_run: bool,
+    },
+    MergeTags {
+        tags: Vec<String>,
+        into: String,
+        dry_run: bool,
+    },
     SearchTag {
         tag: String,
     },
+    SearchText {
+        query: String,
+    },
+    SearchProperty {
+        key: String,
+        value: Option<String>,
+    },
+    Query {
+        query: String,
+        sort: QuerySort,
+        limit: Option<usize>,
+    },
+    RunQuery {
+        name: String,
+        sort: QuerySort,
+        limit: Option<usize>,
+    },
+    Doctor {
+        strict: bool,
+    },
+    GraphExport {
+        filter: GraphFilter,
+    },
+    Fix {
+        fixes: Vec<Fix>,
+        stubs: Vec<String>,
+    },
+    InsertBlockId {
+        note: String,
+        line: usize,
+    },
     ScriptRun {
         script_name: String,
         args: Vec<String>,
@@ -65,32 +161,221 @@ pub enum SovFeature {
 
 impl Sov {
     pub fn new() -> Result<Self> {
+        let mut sov = Sov::load()?;
+        sov.index()?;
+        Ok(sov)
+    }
+
+    /// Loads the config and the DB without indexing the notes dir
+    pub fn load() -> Result<Self> {
         let config = SovConfig::load()?;
-        let sov_db = SovDb::new(&config.db_path)?;
+        let sov_db = SovDb::new(&config.db_path, config.toml.link_matching)?;
+        sov_db.init()?;
 
-        let mut sov = Sov { config, db: sov_db };
-        sov.init()?;
-        Ok(sov)
+        Ok(Sov {
+            config,
+            db: sov_db,
+            resolvers: RefCell::default(),
+            mention_names: RefCell::default(),
+        })
     }
 
-    pub fn init(&mut self) -> Result<()> {
-        self.db.init()?;
-        self.index()?;
-        Ok(())
+    pub fn index(&mut self) -> Result<IndexReport> {
+        self.index_with_progress(|_| ())
     }
 
-    pub fn index(&mut self) -> Result<()> {
-        let mut notes = Vec::new();
+    /// Indexes the notes dir, `progress` is called from the parsing threads
+    /// each time a note has been parsed.
+    ///
+    /// The report lists the unreadable notes and all the notes with an invalid
+    /// frontmatter, modified or not.
+    pub fn index_with_progress(
+        &mut self,
+        progress: impl Fn(IndexProgress) + Sync,
+    ) -> Result<IndexReport> {
+        let mut to_parse = Vec::new();
 
-        let walker = WalkDir::new(&self.config.toml.notes_dir).into_iter();
         let mut fs_paths = HashSet::new();
+        let stats = self.db.get_all_note_stats()?;
+
+        for path in self.walk_notes(&self.config.toml.notes_dir)? {
+            let Some(filename) = SovNote::filename(&path) else {
+                continue;
+            };
+
+            fs_paths.insert(path.clone());
+
+            // Do not re-index notes that have not been modified
+            let metadata = std::fs::metadata(&path)?;
+            let old_stat = stats.get(&path);
+            if let Some(old_stat) = old_stat {
+                if old_stat.mtime == NoteStat::mtime(&metadata) && old_stat.size == metadata.size()
+                {
+                    continue;
+                }
+            }
+            to_parse.push((path, filename));
+        }
+
+        // Reading and parsing is done in parallel, only the DB writes are serial
+        let total = to_parse.len();
+        let parsed = AtomicUsize::new(0);
+        let notes: Vec<_> = to_parse
+            .into_par_iter()
+            .map(|(path, filename)| {
+                let note = Self::parse_note(path, filename);
+                let parsed = parsed.fetch_add(1, Ordering::Relaxed) + 1;
+                progress(IndexProgress { parsed, total });
+                note
+            })
+            .collect();
+        let (notes, mut errors) = Self::partition_notes(notes);
+
+        let mut new_notes = Vec::new();
+        for note in notes {
+            // Only the file time changed (e.g. `touch` or `git checkout`)
+            if stats
+                .get(&note.path)
+                .is_some_and(|s| s.hash == note.stat.hash)
+            {
+                self.db.update_note_stat(&note.path, &note.stat)?;
+                continue;
+            }
+            info!("Indexing new note: {:?} ...", note.path);
+            new_notes.push(note);
+        }
+
+        // Insert new notes
+
+        self.db.insert_notes(&new_notes)?;
+
+        // Clean up DB
+
+        let db_paths = self.db.get_all_note_paths()?;
+        let dead_notes = db_paths.difference(&fs_paths);
+        for note in dead_notes {
+            info!("Deleting dead note: {:?}", note);
+            self.db.delete_note_by_path(note)?;
+        }
+        self.db.clean_dead_tags()?;
+        let indexed = new_notes.into_iter().map(|n| n.path).collect();
+        self.update_link_targets(&indexed)?;
+
+        errors.extend(self.db.get_frontmatter_errors()?);
+        errors.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(IndexReport { errors })
+    }
+
+    /// Re-indexes the given paths only, e.g. the changes reported by a
+    /// [`SovWatcher`].
+    ///
+    /// Paths that no longer exist are removed from the index, directories are
+    /// indexed recursively. The report only lists the errors of the given
+    /// notes.
+    pub fn index_paths(&mut self, paths: &[PathBuf]) -> Result<IndexReport> {
+        let mut notes = Vec::new();
+
+        for path in paths {
+            if self.is_ignored(path) {
+                continue;
+            }
+            if path.is_dir() {
+                for path in self.walk_notes(path)? {
+                    let Some(filename) = SovNote::filename(&path) else {
+                        continue;
+                    };
+                    info!("Indexing new note: {:?} ...", path);
+                    notes.push(Self::parse_note(path, filename));
+                }
+            } else if path.is_file() {
+                if !SovNote::is_note(path) {
+                    continue;
+                }
+                let Some(filename) = SovNote::filename(path) else {
+                    continue;
+                };
+                info!("Indexing new note: {:?} ...", path);
+                notes.push(Self::parse_note(path.clone(), filename));
+            } else {
+                // The path may have been a note or a whole directory of notes
+                info!("Deleting dead path: {:?}", path);
+                self.db.delete_note_by_path(path)?;
+                self.db.delete_notes_in_dir(path)?;
+            }
+        }
+
+        let (notes, mut errors) = Self::partition_notes(notes);
+        self.db.insert_notes(&notes)?;
+        self.db.clean_dead_tags()?;
+        let indexed = notes.iter().map(|n| n.path.clone()).collect();
+        self.update_link_targets(&indexed)?;
+
+        errors.extend(notes.into_iter().filter_map(|note| {
+            Some(NoteError {
+                error: note.frontmatter_error?,
+                path: note.path,
+            })
+        }));
+        Ok(IndexReport { errors })
+    }
+
+    fn parse_note(path: PathBuf, filename: String) -> std::result::Result<SovNote, NoteError> {
+        SovNote::new(path.clone(), filename).map_err(|e| NoteError {
+            path,
+            error: e.to_string(),
+        })
+    }
 
+    /// Splits the parsed notes from the unreadable ones
+    fn partition_notes(
+        notes: Vec<std::result::Result<SovNote, NoteError>>,
+    ) -> (Vec<SovNote>, Vec<NoteError>) {
+        let mut parsed = Vec::new();
+        let mut errors = Vec::new();
+        for note in notes {
+            match note {
+                Ok(note) => parsed.push(note),
+                Err(e) => errors.push(e),
+            }
+        }
+        (parsed, errors)
+    }
+
+    /// Watches the notes dir, see [`Sov::index_paths`] to apply the changes
+    pub fn watcher(&self) -> Result<SovWatcher> {
+        SovWatcher::new(&self.config.toml.notes_dir)
+    }
+
+    /// Whether a normalized path, existing or not, is under the notes dir once
+    /// its symlinks are resolved
+    fn is_in_notes_dir(&self, path: &Path) -> bool {
+        let Ok(notes_dir) = self.config.toml.notes_dir.canonicalize() else {
+            return false;
+        };
+        path.ancestors()
+            .find(|p| p.exists())
+            .and_then(|p| p.canonicalize().ok())
+            .is_some_and(|p| p.starts_with(notes_dir))
+    }
+
+    fn is_ignored(&self, path: &Path) -> bool {
+        self.config
+            .toml
+            .ignore_dirs
+            .iter()
+            .any(|dir| path.starts_with(dir))
+    }
+
+    /// Recursively lists all the notes in `dir`, skipping ignored directories
+    fn walk_notes(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        let walker = WalkDir::new(dir).into_iter();
         for entry in walker.filter_entry(|e| {
             let p = e.path();
             if self.config.toml.ignore_dirs.contains(&p.to_path_buf()) {
                 return false;
             }
-            if p.is_file() && p.extension().map(|s| s == "md").unwrap_or(false) {
+            if p.is_file() && SovNote::is_note(p) {
                 return true;
             }
             if p.is_dir() {
@@ -103,69 +388,474 @@ impl Sov {
             if !entry.path().is_file() {
                 continue;
             }
+            paths.push(entry.into_path());
+        }
+        Ok(paths)
+    }
 
-            let path = entry.path().to_path_buf();
-            let Some(filename) = path.file_stem() else {
-                continue;
-            };
-            let Some(filename) = filename.to_str() else {
-                continue;
-            };
-
-            fs_paths.insert(entry.path().to_path_buf());
+    /// Resolves a note name, either a filename (e.g. `index`), an alias or a
+    /// path-qualified name (e.g. `projects/sov/index`).
+    ///
+    /// Fails if several notes match.
+    pub fn resolve_note(&self, name: &str) -> Result<Option<PathBuf>> {
+        Ok(self.resolve_note_id(name)?.map(|(_, path)| path))
+    }
 
-            // Do not re-index notes that have not been modified
-            let metadata = entry.metadata()?;
-            let ctime = DateTime::from_timestamp(metadata.ctime(), metadata.ctime_nsec() as u32)
-                .ok_or(SovError::InvalidTime)?;
-            if ctime < self.config.last_update {
-                continue;
-            }
-            info!("Indexing new note: {:?} ...", entry.path());
+    fn resolve_note_id(&self, name: &str) -> Result<Option<(u64, PathBuf)>> {
+        Self::resolve_note_id_with(&*self.resolver()?, name)
+    }
 
-            let filename = filename.to_string();
-            let note = SovNote::new(path, filename)?;
-            notes.push(note);
+    fn resolve_note_id_with(resolver: &Resolver, name: &str) -> Result<Option<(u64, PathBuf)>> {
+        match resolver.resolve(name) {
+            Target::Missing => Ok(None),
+            Target::Note(note_id, path) => Ok(Some((note_id, path))),
+            Target::Ambiguous(paths) => Err(SovError::AmbiguousNote(name.to_string(), paths.len())),
         }
+    }
 
-        // Insert new notes
+    fn resolver(&self) -> Result<Arc<Resolver>> {
+        self.resolver_with(self.config.toml.link_matching)
+    }
 
-        self.db.insert_notes(&notes)?;
-        self.config.update_last_update()?;
+    /// The resolver of `matching`, built once until the next index
+    fn resolver_with(&self, matching: LinkMatching) -> Result<Arc<Resolver>> {
+        if let Some(resolver) = self.resolvers.borrow().get(&matching) {
+            return Ok(resolver.clone());
+        }
+        let notes = self.db.get_all_notes()?;
+        let aliases = self.db.get_all_aliases()?;
+        let resolver = Arc::new(Resolver::new(
+            &self.config.toml.notes_dir,
+            matching,
+            notes,
+            aliases,
+        ));
+        self.resolvers
+            .borrow_mut()
+            .insert(matching, resolver.clone());
+        Ok(resolver)
+    }
 
-        // Clean up DB
+    /// Resolves the target note of the links once the notes at `indexed` have
+    /// been indexed or removed.
+    ///
+    /// Only the links of these notes and the links matching a filename or an
+    /// alias whose notes changed are resolved again.
+    fn update_link_targets(&mut self, indexed: &HashSet<PathBuf>) -> Result<()> {
+        let matching = self.config.toml.link_matching;
+        let old = self.resolvers.get_mut().remove(&matching);
+        self.resolvers.get_mut().clear();
+        self.mention_names.get_mut().take();
+        let resolver = self.resolver()?;
+        // Without a previous resolver, all links are resolved
+        let changed = old.map(|old| resolver.changed_keys(&old));
+        let is_affected = |src_path: &Path, value: &str| match &changed {
+            Some(keys) => indexed.contains(src_path) || keys.contains(&resolver.link_key(value)),
+            None => true,
+        };
+        self.db
+            .update_link_targets(is_affected, |src_path, kind, value, path| {
+                match resolver.resolve_link(src_path, kind, value, path) {
+                    Target::Missing => (None, 0),
+                    Target::Note(note_id, _) => (Some(note_id), 1),
+                    Target::Ambiguous(paths) => (None, paths.len()),
+                }
+            })
+    }
 
-        let db_paths = self.db.get_all_note_paths()?;
-        let dead_notes = db_paths.difference(&fs_paths);
-        for note in dead_notes {
-            info!("Deleting dead note: {:?}", note);
-            self.db.delete_note_by_path(note)?;
+    /// Resolves the target note of a link of the note at `src_path`, markdown
+    /// links are resolved by their path relative to the note.
+    ///
+    /// Fails if several notes match.
+    pub fn resolve_link(&self, src_path: &Path, link: &Link) -> Result<Option<PathBuf>> {
+        let resolver = self.resolver()?;
+        match resolver.resolve_link(src_path, link.kind, &link.value, link.path.as_deref()) {
+            Target::Missing => Ok(None),
+            Target::Note(_, path) => Ok(Some(path)),
+            Target::Ambiguous(paths) => {
+                Err(SovError::AmbiguousNote(link.value.clone(), paths.len()))
+            }
         }
-        self.db.clean_dead_tags()?;
+    }
 
-        Ok(())
+    /// Resolves a `[[Note#Heading]]` link, the heading is matched by its
+    /// text (case-insensitive) or by its slug
+    pub fn resolve_heading(
+        &self,
+        filename: &str,
+        header: &str,
+    ) -> Result<Option<(PathBuf, Heading)>> {
+        let Some(note_path) = self.resolve_note(filename)? else {
+            return Ok(None);
+        };
+        let heading = self.db.get_heading(&note_path, header)?;
+        Ok(heading.map(|h| (note_path, h)))
     }
 
-    pub fn resolve_note(&self, filename: &str) -> Result<Option<PathBuf>> {
-        let note_path = self.db.get_note_by_filename(filename)?;
-        Ok(note_path)
+    /// Resolves a `[[Note#^id]]` block reference
+    pub fn resolve_block(&self, filename: &str, id: &str) -> Result<Option<(PathBuf, Block)>> {
+        let Some(note_path) = self.resolve_note(filename)? else {
+            return Ok(None);
+        };
+        let block = self.db.get_block(&note_path, id)?;
+        Ok(block.map(|b| (note_path, b)))
     }
 
-    pub fn resolve_backlinks(&self, filename: &str) -> Result<Vec<(PathBuf, Link)>> {
-        let references = self.db.get_backlinks(filename)?;
+    /// Lists the links pointing to a note, optionally only those of the given
+    /// kind
+    pub fn resolve_backlinks(
+        &self,
+        filename: &str,
+        kind: Option<LinkKind>,
+    ) -> Result<Vec<(PathBuf, Link)>> {
+        let Some((note_id, _)) = self.resolve_note_id(filename)? else {
+            return Ok(Vec::new());
+        };
+        let references = self.db.get_backlinks(note_id, kind)?;
         Ok(references)
     }
 
-    pub fn resolve_links(&self, filename: &str) -> Result<Vec<Link>> {
-        let links = self.db.get_links(filename)?;
+    /// Lists the links of a note, optionally only those of the given kind
+    pub fn resolve_links(&self, filename: &str, kind: Option<LinkKind>) -> Result<Vec<Link>> {
+        let Some((note_id, _)) = self.resolve_note_id(filename)? else {
+            return Ok(Vec::new());
+        };
+        let links = self.db.get_links(note_id, kind)?;
         Ok(links)
     }
 
     pub fn resolve_dead_links(&self, filename: &str) -> Result<Vec<Link>> {
-        let dead_links = self.db.get_dead_links(filename)?;
+        let Some((note_id, _)) = self.resolve_note_id(filename)? else {
+            return Ok(Vec::new());
+        };
+        let dead_links = self.db.get_dead_links(note_id)?;
         Ok(dead_links)
     }
 
+    /// Lists the links of a note matching several notes, with the paths of the
+    /// matching notes
+    pub fn resolve_ambiguous_links(&self, filename: &str) -> Result<Vec<(Link, Vec<PathBuf>)>> {
+        let Some((note_id, note_path)) = self.resolve_note_id(filename)? else {
+            return Ok(Vec::new());
+        };
+        let resolver = self.resolver()?;
+        let links = self.db.get_ambiguous_links(note_id)?;
+        let links = links
+            .into_iter()
+            .filter_map(|link| {
+                let target =
+                    resolver.resolve_link(&note_path, link.kind, &link.value, link.path.as_deref());
+                match target {
+                    Target::Ambiguous(paths) => Some((link, paths)),
+                    _ => None,
+                }
+            })
+            .collect();
+        Ok(links)
+    }
+
+    /// Lists the links of a note that only match their target note once
+    /// case-folded or normalized, see [`LinkMatching`], with the path of the
+    /// target note
+    pub fn resolve_inexact_links(&self, filename: &str) -> Result<Vec<(Link, PathBuf)>> {
+        if self.config.toml.link_matching.is_exact() {
+            return Ok(Vec::new());
+        }
+        let Some((note_id, note_path)) = self.resolve_note_id(filename)? else {
+            return Ok(Vec::new());
+        };
+        let resolver = self.resolver()?;
+        let exact = self.resolver_with(LinkMatching::default())?;
+        let links = self.db.get_links(note_id, None)?;
+        let links = links
+            .into_iter()
+            .filter_map(|link| {
+                let resolve = |r: &Resolver| {
+                    r.resolve_link(&note_path, link.kind, &link.value, link.path.as_deref())
+                };
+                match (resolve(&resolver), resolve(&exact)) {
+                    (Target::Note(id, _), Target::Note(exact_id, _)) if id == exact_id => None,
+                    (Target::Note(_, path), _) => Some((link, path)),
+                    _ => None,
+                }
+            })
+            .collect();
+        Ok(links)
+    }
+
+    /// Lists the plain-text mentions of a note's filename and aliases in the
+    /// other notes, with the wiki-link to replace each of them with.
+    ///
+    /// Mentions are matched case-insensitively as whole words, outside of
+    /// the frontmatter, links, code and HTML comments.
+    pub fn resolve_unlinked_mentions(&self, filename: &str) -> Result<Vec<Mention>> {
+        let Some((note_id, note_path)) = self.resolve_note_id(filename)? else {
+            return Ok(Vec::new());
+        };
+        let mut names = vec![SovNote::filename(&note_path).unwrap_or_default()];
+        for (alias, id) in self.db.get_all_aliases()? {
+            if id == note_id {
+                names.push(alias);
+            }
+        }
+
+        // Only the notes containing one of the names are parsed
+        let query = names
+            .iter()
+            .map(|n| Query::Text(n.clone()))
+            .reduce(|a, b| Query::Or(Box::new(a), Box::new(b)))
+            .expect("the filename is always a name");
+        let sql = self.query_sql(&query)?;
+        let paths = self.db.find_notes_by_query(&sql, QuerySort::Path, None)?;
+
+        let resolver = self.resolver()?;
+        let mut mentions = Vec::new();
+        for path in paths.into_iter().filter(|p| *p != note_path) {
+            let content = std::fs::read_to_string(&path)?;
+            let text = Rope::from_str(&content);
+            for (start, end) in SovNote::parse_mentions(&content, &names) {
+                let mention = text.slice(start..=end).to_string();
+                mentions.push(Mention {
+                    path: path.clone(),
+                    line: text.char_to_line(start) + 1,
+                    start,
+                    end,
+                    link: Self::mention_link(&resolver, note_id, &note_path, &mention),
+                    text: mention,
+                });
+            }
+        }
+        Ok(mentions)
+    }
+
+    /// The filenames and aliases of all notes, to find their mentions in a
+    /// note, e.g. an unsaved buffer.
+    ///
+    /// The names are cached until the next index.
+    pub fn mention_names(&self) -> Result<Arc<MentionNames>> {
+        if let Some(names) = self.mention_names.borrow().as_ref() {
+            return Ok(names.clone());
+        }
+        let notes = self.db.get_all_notes()?;
+        let mut by_name: HashMap<String, HashSet<u64>> = HashMap::new();
+        for (note_id, note_path) in &notes {
+            let name = SovNote::filename(note_path).unwrap_or_default();
+            by_name
+                .entry(name.to_lowercase())
+                .or_default()
+                .insert(*note_id);
+        }
+        for (alias, note_id) in self.db.get_all_aliases()? {
+            by_name
+                .entry(alias.to_lowercase())
+                .or_default()
+                .insert(note_id);
+        }
+        let names = Arc::new(MentionNames {
+            names: by_name.keys().cloned().collect(),
+            by_name,
+            note_paths: notes.into_iter().collect(),
+            resolver: self.resolver()?,
+        });
+        *self.mention_names.borrow_mut() = Some(names.clone());
+        Ok(names)
+    }
+
+    /// The wiki-link replacing a mention of a note, to the filename or to the
+    /// path if other notes share it
+    fn mention_link(resolver: &Resolver, note_id: u64, note_path: &Path, mention: &str) -> String {
+        let resolves_to_note =
+            |n: &str| matches!(resolver.resolve(n), Target::Note(id, _) if id == note_id);
+        if resolves_to_note(mention) {
+            return format!("[[{}]]", mention);
+        }
+        let name = SovNote::filename(note_path).unwrap_or_default();
+        let target = match resolves_to_note(&name) {
+            true => name,
+            false => resolver
+                .qualified_name(note_path)
+                .unwrap_or_else(|| note_path.with_extension("").to_string_lossy().to_string()),
+        };
+        format!("[[{}|{}]]", target, mention)
+    }
+
+    /// Runs the enabled [`LintRule`]s over all notes, the problems are sorted by
+    /// path and position
+    pub fn lint(&self) -> Result<Vec<Lint>> {
+        self.lint_notes(None, &*self.resolver()?)
+    }
+
+    /// Runs the enabled [`LintRule`]s over a single note
+    pub fn lint_note(&self, filename: &str) -> Result<Vec<Lint>> {
+        let resolver = self.resolver()?;
+        let Some(note) = Self::resolve_note_id_with(&resolver, filename)? else {
+            return Ok(Vec::new());
+        };
+        self.lint_notes(Some(note), &resolver)
+    }
+
+    /// Lints all notes, or only the `(note_id, path)` note
+    fn lint_notes(&self, only: Option<(u64, PathBuf)>, resolver: &Resolver) -> Result<Vec<Lint>> {
+        let config = &self.config.toml.lints;
+        let enabled = |rule: LintRule| rule.level(config) != LintLevel::Off;
+        let mut lints = Vec::new();
+        let mut push = |rule: LintRule, path: PathBuf, span, message| {
+            let level = rule.level(config);
+            if level == LintLevel::Off {
+                return;
+            }
+            lints.push(Lint {
+                rule,
+                level,
+                path,
+                line: None,
+                span,
+                message,
+            });
+        };
+
+        let frontmatter_errors = match &only {
+            Some((id, path)) => self
+                .db
+                .get_frontmatter_error(*id)?
+                .map(|error| NoteError {
+                    path: path.clone(),
+                    error,
+                })
+                .into_iter()
+                .collect(),
+            None => self.db.get_frontmatter_errors()?,
+        };
+        for e in frontmatter_errors {
+            push(LintRule::InvalidFrontmatter, e.path, None, e.error);
+        }
+
+        let resolve = |path: &Path, link: &Link| {
+            resolver.resolve_link(path, link.kind, &link.value, link.path.as_deref())
+        };
+        if enabled(LintRule::DeadLink)
+            || enabled(LintRule::MissingHeading)
+            || enabled(LintRule::MissingBlock)
+        {
+            let dead_links: Vec<_> = match &only {
+                Some((id, path)) => {
+                    let links = self.db.get_dead_links(*id)?;
+                    links.into_iter().map(|l| (path.clone(), l)).collect()
+                }
+                None => self.db.get_all_dead_links()?,
+            };
+            for (path, link) in dead_links {
+                let (rule, message) = LintRule::dead_link(&link, &resolve(&path, &link));
+                push(rule, path, Some((link.start, link.end)), message);
+            }
+        }
+        if enabled(LintRule::AmbiguousLink) {
+            let ambiguous_links: Vec<_> = match &only {
+                Some((id, path)) => {
+                    let links = self.db.get_ambiguous_links(*id)?;
+                    links.into_iter().map(|l| (path.clone(), l)).collect()
+                }
+                None => self.db.get_all_ambiguous_links()?,
+            };
+            for (path, link) in ambiguous_links {
+                let Target::Ambiguous(targets) = resolve(&path, &link) else {
+                    continue;
+                };
+                let targets: Vec<_> = targets.iter().map(|p| p.display().to_string()).collect();
+                let message = format!(
+                    "`{}` matches several notes, use a path-qualified link: {}",
+                    link.value,
+                    targets.join(", ")
+                );
+                push(
+                    LintRule::AmbiguousLink,
+                    path,
+                    Some((link.start, link.end)),
+                    message,
+                );
+            }
+        }
+        if enabled(LintRule::DuplicateName) {
+            let duplicates = match &only {
+                Some((id, _)) => DuplicateNotes::group(self.db.get_namesakes(*id)?),
+                None => self.list_duplicates()?,
+            };
+            for duplicate in duplicates {
+                for path in &duplicate.paths {
+                    if only.as_ref().is_some_and(|(_, p)| p != path) {
+                        continue;
+                    }
+                    let others: Vec<_> = duplicate
+                        .paths
+                        .iter()
+                        .filter(|p| *p != path)
+                        .map(|p| p.display().to_string())
+                        .collect();
+                    let message = format!(
+                        "filename `{}` is shared with {}",
+                        duplicate.filename,
+                        others.join(", ")
+                    );
+                    push(LintRule::DuplicateName, path.clone(), None, message);
+                }
+            }
+        }
+        if enabled(LintRule::EmptyNote) {
+            let empty_notes = match &only {
+                Some((id, path)) => match self.db.is_empty_note(*id)? {
+                    true => vec![path.clone()],
+                    false => Vec::new(),
+                },
+                None => self.db.get_empty_notes()?,
+            };
+            for path in empty_notes {
+                push(LintRule::EmptyNote, path, None, "the note is empty".into());
+            }
+        }
+        if enabled(LintRule::OrphanNote) {
+            let orphans = match &only {
+                Some((id, path)) => match self.db.is_orphaned_note(*id)? {
+                    true => vec![path.clone()],
+                    false => Vec::new(),
+                },
+                None => self.db.get_all_orphaned_notes()?,
+            };
+            for path in orphans {
+                let message = "no note links to this note".into();
+                push(LintRule::OrphanNote, path, None, message);
+            }
+        }
+
+        lints.sort_by(|a, b| a.path.cmp(&b.path).then(a.span.cmp(&b.span)));
+        Self::fill_lint_lines(&mut lints);
+        Ok(lints)
+    }
+
+    /// Sets the line of the lints with a span, `lints` being sorted by path
+    fn fill_lint_lines(lints: &mut [Lint]) {
+        let mut content: Option<(PathBuf, Option<Rope>)> = None;
+        for lint in lints.iter_mut() {
+            let Some((start, _)) = lint.span else {
+                continue;
+            };
+            if content.as_ref().is_none_or(|(path, _)| *path != lint.path) {
+                // The note may have changed since it was indexed
+                let rope = File::open(&lint.path)
+                    .and_then(|f| Rope::from_reader(BufReader::new(f)))
+                    .ok();
+                content = Some((lint.path.clone(), rope));
+            }
+            if let Some((_, Some(rope))) = &content {
+                lint.line = rope.try_char_to_line(start).ok().map(|l| l + 1);
+            }
+        }
+    }
+
+    /// Lists the notes sharing their filename with other notes
+    pub fn list_duplicates(&self) -> Result<Vec<DuplicateNotes>> {
+        Ok(DuplicateNotes::group(self.db.get_duplicate_notes()?))
+    }
+
     pub fn list_note_names(&self) -> Result<Vec<String>> {
         let notes = self.db.get_all_note_names()?;
         Ok(notes)
@@ -176,10 +866,26 @@ impl Sov {
         Ok(aliases)
     }
 
-    pub fn list_tags(&self) -> Result<Vec<String>> {
-        // TODO: display and sort by count
-        let unique_tags = self.db.get_unique_tags()?;
-        Ok(unique_tags)
+    /// Lists the tags used by at least `min_count` notes, with the notes using
+    /// them
+    pub fn list_tags(&self, sort: TagSort, min_count: usize) -> Result<Vec<TagUsage>> {
+        let mut tags = self.db.get_tag_usages()?;
+        tags.retain(|t| t.count >= min_count);
+        TagUsage::sort(&mut tags, sort);
+        Ok(tags)
+    }
+
+    /// Lists the nested tags as a tree, e.g. `project/sov/lsp` is listed under
+    /// `project` and `project/sov`
+    pub fn list_tag_tree(&self) -> Result<Vec<TagNode>> {
+        let tag_notes = self.db.get_tag_notes()?;
+        Ok(TagNode::build_tree(&tag_notes))
+    }
+
+    /// Lists the frontmatter property keys with the number of notes using them
+    pub fn list_properties(&self) -> Result<Vec<PropertyCount>> {
+        let keys = self.db.get_property_keys()?;
+        Ok(keys)
     }
 
     pub fn list_orphans(&self) -> Result<Vec<PathBuf>> {
@@ -187,11 +893,116 @@ impl Sov {
         Ok(orphans)
     }
 
-    pub fn list_dead_links(&self) -> Result<Vec<(PathBuf, String)>> {
+    pub fn list_dead_links(&self) -> Result<Vec<(PathBuf, Link)>> {
         let dead_links = self.db.get_all_dead_links()?;
         Ok(dead_links)
     }
 
+    /// Builds the graph of the notes and their links, restricted by `filter`.
+    ///
+    /// Ambiguous links and image embeds are not part of the graph, dead links
+    /// point to a node without a path.
+    pub fn graph(&self, filter: &GraphFilter) -> Result<Graph> {
+        let toml = &self.config.toml;
+        let node_id = |path: &Path| {
+            let relative = path.strip_prefix(&toml.notes_dir).unwrap_or(path);
+            relative.to_string_lossy().to_string()
+        };
+        let root = match &filter.root {
+            Some(root) => Some(
+                self.resolve_note_id(root)?
+                    .ok_or_else(|| SovError::NoteNotFound(root.clone()))?,
+            ),
+            None => None,
+        };
+
+        let notes = self.db.get_all_notes()?;
+        let query = filter
+            .tag
+            .iter()
+            .map(|tag| Query::Tag(tag.clone()))
+            .chain(filter.path.iter().map(|path| Query::Path(path.clone())))
+            .reduce(|a, b| Query::And(Box::new(a), Box::new(b)));
+        let included: HashSet<PathBuf> = match query {
+            Some(query) => {
+                let sql = self.query_sql(&query)?;
+                let paths = self.db.find_notes_by_query(&sql, QuerySort::Path, None)?;
+                paths.into_iter().collect()
+            }
+            None => notes.iter().map(|(_, path)| path.clone()).collect(),
+        };
+
+        let mut tags: HashMap<u64, Vec<String>> = HashMap::new();
+        for (tag, note_id) in self.db.get_tag_notes()? {
+            tags.entry(note_id).or_default().push(tag);
+        }
+        let paths: HashMap<u64, PathBuf> = notes.iter().cloned().collect();
+        let mut ids = HashMap::new();
+        let mut nodes = Vec::new();
+        for (note_id, path) in notes {
+            // The root is kept even if filtered out, as the center of the graph
+            let is_root = matches!(&root, Some((id, _)) if *id == note_id);
+            if !is_root && !included.contains(&path) {
+                continue;
+            }
+            let mut note_tags = tags.remove(&note_id).unwrap_or_default();
+            note_tags.sort();
+            ids.insert(note_id, node_id(&path));
+            nodes.push(GraphNode {
+                id: node_id(&path),
+                label: SovNote::filename(&path).unwrap_or_default(),
+                path: Some(path),
+                tags: note_tags,
+            });
+        }
+
+        // A missing note is linked to by the links that would resolve to it,
+        // by their path for markdown links, by their value otherwise
+        let missing_id = |src_note: u64, kind: LinkKind, value: &str, path: Option<&str>| match (
+            kind,
+            path,
+            paths.get(&src_note),
+        ) {
+            (LinkKind::Markdown, Some(path), Some(src_path)) => {
+                let dir = src_path.parent().unwrap_or(Path::new(""));
+                node_id(&Resolver::normalize(&dir.join(path)))
+            }
+            _ => toml.link_matching.key(value),
+        };
+        let mut links = Vec::new();
+        let mut missing = BTreeMap::new();
+        for (src_note, target_note, kind, value, path) in self.db.get_link_graph()? {
+            let Some(source) = ids.get(&src_note) else {
+                continue;
+            };
+            let target = match target_note {
+                Some(target_note) => match ids.get(&target_note) {
+                    Some(target) => target.clone(),
+                    None => continue,
+                },
+                None if filter.dead_links => {
+                    let id = missing_id(src_note, kind, &value, path.as_deref());
+                    missing.entry(id.clone()).or_insert(value);
+                    id
+                }
+                None => continue,
+            };
+            links.push((source.clone(), target));
+        }
+        nodes.extend(missing.into_iter().map(|(id, value)| GraphNode {
+            id,
+            label: value.rsplit('/').next().unwrap_or_default().to_string(),
+            path: None,
+            tags: Vec::new(),
+        }));
+
+        let graph = Graph::new(nodes, links);
+        match root {
+            Some((_, path)) => Ok(graph.around(&node_id(&path), filter.depth)),
+            None => Ok(graph),
+        }
+    }
+
     pub fn list_scripts(&self) -> Result<Vec<String>> {
         let scripts = self.config.toml.scripts_dir.read_dir()?;
         let scripts = scripts
@@ -215,7 +1026,7 @@ impl Sov {
         let date = now.format("%Y-%m-%d").to_string();
 
         // if note already exists
-        if let Some(path) = self.db.get_note_by_filename(&date)? {
+        if let Target::Note(_, path) = self.resolver()?.resolve(&date) {
             return Ok(path);
         }
         let path = self
@@ -224,6 +1035,9 @@ impl Sov {
             .daily_notes_dir
             .join(&date)
             .with_extension("md");
+        if path.exists() {
+            return Ok(path);
+        }
         info!("Creating new daily note: {:?}", path);
         if !self.config.toml.daily_notes_script.is_empty() {
             let content = self.script_run(&self.config.toml.daily_notes_script, vec![])?;
@@ -267,33 +1081,336 @@ impl Sov {
         Ok(note_path)
     }
 
+    /// Lists the notes tagged with `tag` or with one of its nested tags, e.g.
+    /// `project/sov` for `project`
     pub fn search_tag(&self, tag: &str) -> Result<Vec<PathBuf>> {
         let notes = self.db.find_notes_by_tag(tag)?;
         Ok(notes)
     }
 
+    /// Lists the notes having the frontmatter property `key`, with the given
+    /// value if any. List values match if any of their items matches.
+    pub fn search_property(&self, key: &str, value: Option<&str>) -> Result<Vec<PathBuf>> {
+        let notes = self.db.find_notes_by_property(key, value)?;
+        Ok(notes)
+    }
+
+    /// Lists the notes matching a [`Query`] expression, e.g.
+    /// `tag:project AND prop.status=open AND path:work/`
+    pub fn query(
+        &self,
+        query: &str,
+        sort: QuerySort,
+        limit: Option<usize>,
+    ) -> Result<Vec<PathBuf>> {
+        let query = self.query_sql(&Query::parse(query)?)?;
+        let notes = self.db.find_notes_by_query(&query, sort, limit)?;
+        Ok(notes)
+    }
+
+    /// Compiles a query, the notes of `links-to:` terms are resolved like
+    /// links and must match a single note
+    fn query_sql(&self, query: &Query) -> Result<QuerySql> {
+        let resolve = |name: &str| match self.resolve_note_id(name)? {
+            Some((note_id, _)) => Ok(note_id),
+            None => Err(SovError::NoteNotFound(name.to_string())),
+        };
+        query.to_sql(&self.config.toml.notes_dir, &resolve)
+    }
+
+    /// The saved queries of the config, by name
+    pub fn saved_queries(&self) -> &BTreeMap<String, String> {
+        &self.config.toml.queries
+    }
+
+    /// Lists the notes matching the saved query `name`
+    pub fn run_query(
+        &self,
+        name: &str,
+        sort: QuerySort,
+        limit: Option<usize>,
+    ) -> Result<Vec<PathBuf>> {
+        let query = self
+            .saved_queries()
+            .get(name)
+            .ok_or_else(|| SovError::QueryNotFound(name.to_string()))?;
+        self.query(query, sort, limit)
+    }
+
+    /// Full-text search over note titles, aliases and content.
+    ///
+    /// Notes containing all the words of the query are listed, a trailing `*`
+    /// searches a prefix. Results are sorted by relevance.
+    pub fn search_text(&self, query: &str) -> Result<Vec<TextMatch>> {
+        let query = query::fts_terms(query);
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+        let matches = self.db.search_text(&query)?;
+        Ok(matches)
+    }
+
+    /// Appends a new `^id` block marker to the 0-based `line` of a note and
+    /// returns the id. The existing id is returned if the line already has one.
+    ///
+    /// Fails for blank lines and lines of the frontmatter, code blocks and HTML
+    /// comments, see [`SovNote::accepts_block_id`].
+    pub fn insert_block_id(&self, filename: &str, line: usize) -> Result<String> {
+        let path = self
+            .resolve_note(filename)?
+            .ok_or(SovError::NoteNotFound(filename.to_string()))?;
+        let content = std::fs::read_to_string(&path)?;
+        if !SovNote::accepts_block_id(&content, line) {
+            return Err(SovError::InvalidLine(line));
+        }
+        let mut text = Rope::from_str(&content);
+
+        let line_str = text.line(line).to_string();
+        if let Some(id) = Block::parse_id(&line_str) {
+            return Ok(id.to_string());
+        }
+        let line_str = line_str.trim_end_matches(['\n', '\r']);
+
+        let id = Block::new_id(&path, line, &SovNote::parse_blocks(&content));
+        let end = text.line_to_char(line) + line_str.chars().count();
+        text.insert(end, &format!(" ^{}", id));
+        text.write_to(&mut File::create(&path)?)?;
+        Ok(id)
+    }
+
+    /// Renames a tag and its nested tags in the frontmatter and the content of
+    /// all notes, see [`Sov::merge_tags`]
+    pub fn rename_tag(&self, old_tag: &str, new_tag: &str, dry_run: bool) -> Result<Vec<NoteEdit>> {
+        self.merge_tags(&[old_tag.to_string()], new_tag, dry_run)
+    }
+
+    /// Renames several tags and their nested tags into `into` in the
+    /// frontmatter and the content of all notes.
+    ///
+    /// Returns the edits made, or that would be made with `dry_run`.
+    pub fn merge_tags(&self, tags: &[String], into: &str, dry_run: bool) -> Result<Vec<NoteEdit>> {
+        let rename = TagRename { from: tags, into };
+        let mut edits = Vec::new();
+        for path in self.find_notes_by_tags(tags)? {
+            let content = std::fs::read_to_string(&path)?;
+            let new_content = rename.apply(&content);
+            if new_content == content {
+                continue;
+            }
+            let edit = NoteEdit::new(path, &content, new_content);
+            if !dry_run {
+                info!("Updating tags in {:?}", edit.path);
+                edit.write()?;
+            }
+            edits.push(edit);
+        }
+        Ok(edits)
+    }
+
+    /// Lists the notes using one of `tags` or their nested tags
+    pub fn find_notes_by_tags(&self, tags: &[String]) -> Result<BTreeSet<PathBuf>> {
+        let mut paths = BTreeSet::new();
+        for tag in tags {
+            paths.extend(self.db.find_notes_by_tag(tag)?);
+        }
+        Ok(paths)
+    }
+
+    /// Computes the edits of the automatic `fixes`, and creates a stub note for
+    /// each dead link of `stubs` (e.g. `Some Note` for `[[Some Note]]`).
+    ///
+    /// Nothing is written, see [`NoteEdit::write`]. All the fixes of a note
+    /// are combined in a single edit.
+    pub fn fix(&self, fixes: &[Fix], stubs: &[String]) -> Result<Vec<NoteEdit>> {
+        let resolver = self.resolver()?;
+        let resolve = |path: &Path, link: &Link| {
+            resolver.resolve_link(path, link.kind, &link.value, link.path.as_deref())
+        };
+        // Rewritten links, with their original offsets
+        let mut links: BTreeMap<PathBuf, Vec<Link>> = BTreeMap::new();
+
+        if fixes.contains(&Fix::LinkCase) {
+            // Links are matched loosely whatever the configured matching, so
+            // that links left dead by an exact matching are fixed too
+            let folding = LinkMatching {
+                case_insensitive: true,
+                unicode_normalization: true,
+            };
+            let loose = self.resolver_with(folding)?;
+            let exact = self.resolver_with(LinkMatching::default())?;
+            let mut aliases: HashMap<u64, Vec<String>> = HashMap::new();
+            for (alias, note_id) in self.db.get_all_aliases()? {
+                aliases.entry(note_id).or_default().push(alias);
+            }
+            let mut candidates = self.db.get_all_resolved_links()?;
+            let dead_links = self.db.get_all_dead_links()?.into_iter();
+            candidates.extend(
+                dead_links.filter(|(path, link)| matches!(resolve(path, link), Target::Missing)),
+            );
+            for (path, mut link) in candidates {
+                let Target::Note(note_id, target) =
+                    loose.resolve_link(&path, link.kind, &link.value, link.path.as_deref())
+                else {
+                    continue;
+                };
+                let exact_target =
+                    exact.resolve_link(&path, link.kind, &link.value, link.path.as_deref());
+                if link.kind == LinkKind::Markdown
+                    || matches!(exact_target, Target::Note(id, _) if id == note_id)
+                {
+                    continue;
+                }
+                // The link matches either the filename or an alias
+                let name = folding.key(link.value.rsplit('/').next().unwrap_or_default());
+                let filename = SovNote::filename(&target).unwrap_or_default();
+                let canonical = std::iter::once(&filename)
+                    .chain(aliases.get(&note_id).into_iter().flatten())
+                    .find(|n| folding.key(n) == name);
+                if let Some(canonical) = canonical {
+                    link.rename_target(canonical);
+                    links.entry(path).or_default().push(link);
+                }
+            }
+        }
+
+        if fixes.contains(&Fix::AmbiguousLinks) {
+            let notes_dir = &self.config.toml.notes_dir;
+            for (path, mut link) in self.db.get_all_ambiguous_links()? {
+                let Target::Ambiguous(targets) = resolve(&path, &link) else {
+                    continue;
+                };
+                // The matching note sharing the most parent dirs with the
+                // note, if there is a single one
+                let dir = path.parent().unwrap_or(notes_dir);
+                let closeness = |target: &PathBuf| {
+                    let target_dir = target.parent().unwrap_or(notes_dir);
+                    dir.components()
+                        .zip(target_dir.components())
+                        .take_while(|(a, b)| a == b)
+                        .count()
+                };
+                let max = targets.iter().map(closeness).max().unwrap_or_default();
+                let mut closest = targets.iter().filter(|t| closeness(t) == max);
+                let (Some(target), None) = (closest.next(), closest.next()) else {
+                    continue;
+                };
+                // The new link must resolve to the target alone
+                let Some(qualified) = resolver.qualified_name(target) else {
+                    continue;
+                };
+                link.value = qualified;
+                links.entry(path).or_default().push(link);
+            }
+        }
+
+        let mut paths: BTreeSet<PathBuf> = links.keys().cloned().collect();
+        if fixes.contains(&Fix::Tags) {
+            let note_ids: HashSet<u64> =
+                self.db.get_tag_notes()?.into_iter().map(|t| t.1).collect();
+            for (note_id, path) in self.db.get_all_notes()? {
+                if note_ids.contains(&note_id) {
+                    paths.insert(path);
+                }
+            }
+        }
+        // The frontmatter of these notes cannot be safely rewritten
+        let invalid: HashSet<PathBuf> = self
+            .db
+            .get_frontmatter_errors()?
+            .into_iter()
+            .map(|e| e.path)
+            .collect();
+
+        let mut edits = Vec::new();
+        for path in paths {
+            let content = std::fs::read_to_string(&path)?;
+            let mut text = Rope::from_str(&content);
+            let mut note_links = links.remove(&path).unwrap_or_default();
+            // Edit from the end so that the offsets of the other links stay valid
+            note_links.sort_by_key(|l| std::cmp::Reverse(l.start));
+            for link in note_links {
+                text.remove(link.start..=link.end);
+                text.insert(link.start, &link.to_string());
+            }
+            let mut new_content = text.to_string();
+            if fixes.contains(&Fix::Tags) && !invalid.contains(&path) {
+                new_content = tag::normalize_frontmatter_tags(&new_content);
+            }
+            if new_content != content {
+                edits.push(NoteEdit::new(path, &content, new_content));
+            }
+        }
+
+        let dead_links = match stubs.is_empty() {
+            true => Vec::new(),
+            false => self.db.get_all_dead_links()?,
+        };
+        for stub in stubs {
+            let dead_link = dead_links.iter().find(|(path, link)| {
+                link.value == *stub && matches!(resolve(path, link), Target::Missing)
+            });
+            let Some((src_path, link)) = dead_link else {
+                return Err(SovError::NoDeadLink(stub.clone()));
+            };
+            // Markdown links are relative to their note
+            let path = match &link.path {
+                Some(path) => src_path.parent().unwrap_or(Path::new("")).join(path),
+                None => self.config.toml.notes_dir.join(format!("{}.md", stub)),
+            };
+            let path = Resolver::normalize(&path);
+            if !self.is_in_notes_dir(&path) || self.is_ignored(&path) {
+                return Err(SovError::InvalidPath(path));
+            }
+            // e.g. a note of an ignored dir, or a file that is not a note
+            if path.symlink_metadata().is_ok() {
+                return Err(SovError::NoteExists(path));
+            }
+            let title = stub.rsplit('/').next().unwrap_or_default();
+            edits.push(NoteEdit::create(path, format!("# {}\n", title)));
+        }
+        Ok(edits)
+    }
+
     pub fn rename_file(
         &self,
         old_filename: &str,
         new_filename: &str,
         do_rename: bool,
     ) -> Result<PathBuf> {
-        let old_path = self
-            .resolve_note(old_filename)?
+        let (note_id, old_path) = self
+            .resolve_note_id(old_filename)?
             .ok_or(SovError::NoteNotFound(old_filename.to_string()))?;
 
         // Rename all backlinks
-        let backlinks = self.db.get_backlinks(old_filename)?;
-        for (back_path, mut backlink) in backlinks {
-            dbg!(&back_path);
+        let backlinks = self.db.get_backlinks(note_id, None)?;
+        let old_stem = SovNote::filename(&old_path).unwrap_or_default();
+        let matching = &self.config.toml.link_matching;
+        let old_stem = matching.key(&old_stem);
+        let mut backlinks_by_path: BTreeMap<PathBuf, Vec<Link>> = BTreeMap::new();
+        for (back_path, backlink) in backlinks {
+            // Links to an alias stay valid
+            let target = backlink.value.rsplit('/').next().unwrap_or_default();
+            if backlink.kind != LinkKind::Markdown && matching.key(target) != old_stem {
+                continue;
+            }
+            backlinks_by_path
+                .entry(back_path)
+                .or_default()
+                .push(backlink);
+        }
+        for (back_path, mut backlinks) in backlinks_by_path {
+            info!("Updating backlinks in {:?}", back_path);
             let reader = BufReader::new(File::open(&back_path)?);
             let mut text = Rope::from_reader(reader)?;
 
-            text.remove(backlink.start..=backlink.end);
-            // HACK: to call .to_string, but the modified link is not fully correct
-            backlink.value = new_filename.to_string();
-            let link_str = backlink.to_string();
-            text.insert(backlink.start, &link_str);
+            // Edit from the end so that the offsets of the other links stay valid
+            backlinks.sort_by_key(|l| std::cmp::Reverse(l.start));
+            for mut backlink in backlinks {
+                text.remove(backlink.start..=backlink.end);
+                backlink.rename_target(new_filename);
+                let link_str = backlink.to_string();
+                text.insert(backlink.start, &link_str);
+            }
 
             text.write_to(&mut File::create(&back_path)?)?;
         }
@@ -310,3 +1427,61 @@ impl Sov {
         Ok(new_path)
     }
 }
+
+/// The filenames and aliases of the notes, see [`Sov::mention_names`]
+pub struct MentionNames {
+    /// Lowercase names
+    names: Vec<String>,
+    by_name: HashMap<String, HashSet<u64>>,
+    note_paths: HashMap<u64, PathBuf>,
+    resolver: Arc<Resolver>,
+}
+
+impl MentionNames {
+    /// Lists the plain-text mentions of the other notes' filenames and aliases
+    /// in the 0-based `lines` of `text`, the content of the note at `path`.
+    ///
+    /// Only these lines are parsed, from the start of the code block, HTML
+    /// comment or frontmatter they begin in. Mentions matching the names of
+    /// several other notes are left out.
+    pub fn find(&self, path: &Path, text: &Rope, lines: std::ops::Range<usize>) -> Vec<Mention> {
+        let first = SovNote::enclosing_block_line(text, lines.start);
+        let start = text.line_to_char(first.min(text.len_lines()));
+        let end = text.line_to_char(lines.end.min(text.len_lines()));
+        // Past the first line, a leading `---` is a thematic break and not the
+        // frontmatter, which the newline prevents from matching
+        let (content, offset) = match first {
+            0 => (text.slice(start..end).to_string(), start),
+            _ => (format!("\n{}", text.slice(start..end)), start - 1),
+        };
+
+        let mut mentions = Vec::new();
+        for (start, end) in SovNote::parse_mentions(&content, &self.names) {
+            let (start, end) = (start + offset, end + offset);
+            let mention = text.slice(start..=end).to_string();
+            let Some(note_ids) = self.by_name.get(&mention.to_lowercase()) else {
+                continue;
+            };
+            let mut note_ids = note_ids
+                .iter()
+                .filter(|id| self.note_paths.get(id).is_some_and(|p| p != path));
+            let (Some(note_id), None) = (note_ids.next(), note_ids.next()) else {
+                continue;
+            };
+            mentions.push(Mention {
+                path: path.to_path_buf(),
+                line: text.char_to_line(start) + 1,
+                start,
+                end,
+                link: Sov::mention_link(
+                    &self.resolver,
+                    *note_id,
+                    &self.note_paths[note_id],
+                    &mention,
+                ),
+                text: mention,
+            });
+        }
+        mentions
+    }
+}
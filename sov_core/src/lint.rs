@@ -0,0 +1,277 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Result, SovError};
+use crate::note::Link;
+use crate::resolve::Target;
+
+/// A check of the notes, run by [`crate::Sov::lint`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LintRule {
+    /// The frontmatter could not be parsed
+    InvalidFrontmatter,
+    /// A link to a missing note
+    DeadLink,
+    /// A link to a missing heading of an existing note
+    MissingHeading,
+    /// A link to a missing `^block-id` of an existing note
+    MissingBlock,
+    /// A link matching several notes
+    AmbiguousLink,
+    /// A note sharing its filename with other notes
+    DuplicateName,
+    /// A note without any content besides its frontmatter
+    EmptyNote,
+    /// A note not linked to by any other note
+    OrphanNote,
+}
+
+/// Severity of a [`LintRule`], `off` disables the rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LintLevel {
+    Off,
+    Info,
+    Warning,
+    Error,
+}
+
+/// A problem found in a note
+#[derive(Debug, Clone, Serialize)]
+pub struct Lint {
+    pub rule: LintRule,
+    pub level: LintLevel,
+    pub path: PathBuf,
+    /// 1-based line, `None` for problems of the whole note
+    pub line: Option<usize>,
+    /// Char offsets of the problem in the note (e.g. of a link), `end` is
+    /// inclusive
+    pub span: Option<(usize, usize)>,
+    pub message: String,
+}
+
+/// An automatic fix, see [`crate::Sov::fix`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fix {
+    /// Rewrites the frontmatter tags as a list of unique tags
+    Tags,
+    /// Rewrites the links matching a note only case-insensitively or once
+    /// normalized into the exact spelling of the note
+    LinkCase,
+    /// Rewrites ambiguous links into path-qualified links to the closest
+    /// matching note
+    AmbiguousLinks,
+}
+
+impl LintRule {
+    pub const ALL: [LintRule; 8] = [
+        LintRule::InvalidFrontmatter,
+        LintRule::DeadLink,
+        LintRule::MissingHeading,
+        LintRule::MissingBlock,
+        LintRule::AmbiguousLink,
+        LintRule::DuplicateName,
+        LintRule::EmptyNote,
+        LintRule::OrphanNote,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LintRule::InvalidFrontmatter => "invalid-frontmatter",
+            LintRule::DeadLink => "dead-link",
+            LintRule::MissingHeading => "missing-heading",
+            LintRule::MissingBlock => "missing-block",
+            LintRule::AmbiguousLink => "ambiguous-link",
+            LintRule::DuplicateName => "duplicate-name",
+            LintRule::EmptyNote => "empty-note",
+            LintRule::OrphanNote => "orphan-note",
+        }
+    }
+
+    /// The level of the rule unless configured otherwise, see
+    /// [`LintRule::level`]
+    pub fn default_level(&self) -> LintLevel {
+        match self {
+            LintRule::InvalidFrontmatter => LintLevel::Error,
+            LintRule::DeadLink
+            | LintRule::MissingHeading
+            | LintRule::MissingBlock
+            | LintRule::AmbiguousLink
+            | LintRule::DuplicateName => LintLevel::Warning,
+            LintRule::EmptyNote => LintLevel::Info,
+            // Many vaults have standalone notes on purpose
+            LintRule::OrphanNote => LintLevel::Off,
+        }
+    }
+
+    /// The configured level of the rule, see [`crate::config::SovConfigToml`]
+    pub fn level(&self, config: &BTreeMap<LintRule, LintLevel>) -> LintLevel {
+        config
+            .get(self)
+            .copied()
+            .unwrap_or_else(|| self.default_level())
+    }
+
+    /// The rule and message of a dead link given its `target`: a link to an
+    /// existing note misses its heading or block, any other link is dead
+    pub(crate) fn dead_link(link: &Link, target: &Target) -> (LintRule, String) {
+        match (target, &link.header, &link.block) {
+            (Target::Note(_, target), Some(header), _) => (
+                LintRule::MissingHeading,
+                format!("no heading `{}` in {}", header, target.display()),
+            ),
+            (Target::Note(_, target), _, Some(block)) => (
+                LintRule::MissingBlock,
+                format!("no block `^{}` in {}", block, target.display()),
+            ),
+            _ => (
+                LintRule::DeadLink,
+                format!("no note matches `{}`", link.value),
+            ),
+        }
+    }
+}
+
+impl LintLevel {
+    /// The lowest level failing `sov doctor`, warnings only fail when `strict`
+    pub fn fail_level(strict: bool) -> LintLevel {
+        match strict {
+            true => LintLevel::Warning,
+            false => LintLevel::Error,
+        }
+    }
+
+    /// Whether any of the `lints` is at least at the [`LintLevel::fail_level`]
+    pub fn fails(lints: &[Lint], strict: bool) -> bool {
+        let fail_level = Self::fail_level(strict);
+        lints.iter().any(|l| l.level >= fail_level)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LintLevel::Off => "off",
+            LintLevel::Info => "info",
+            LintLevel::Warning => "warning",
+            LintLevel::Error => "error",
+        }
+    }
+}
+
+impl std::fmt::Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        write!(
+            f,
+            ": {}[{}]: {}",
+            self.level.as_str(),
+            self.rule.as_str(),
+            self.message
+        )
+    }
+}
+
+impl Fix {
+    pub const ALL: [Fix; 3] = [Fix::Tags, Fix::LinkCase, Fix::AmbiguousLinks];
+}
+
+impl FromStr for Fix {
+    type Err = SovError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "tags" => Ok(Fix::Tags),
+            "link-case" => Ok(Fix::LinkCase),
+            "ambiguous-links" => Ok(Fix::AmbiguousLinks),
+            _ => Err(SovError::InvalidFix(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::LinkKind;
+
+    fn link(header: Option<&str>, block: Option<&str>) -> Link {
+        Link {
+            kind: LinkKind::Link,
+            value: "a".into(),
+            alias: None,
+            header: header.map(String::from),
+            block: block.map(String::from),
+            path: None,
+            start: 0,
+            end: 4,
+        }
+    }
+
+    fn lint(level: LintLevel) -> Lint {
+        Lint {
+            rule: LintRule::DeadLink,
+            level,
+            path: PathBuf::from("/v/n.md"),
+            line: None,
+            span: None,
+            message: String::new(),
+        }
+    }
+
+    #[test]
+    fn configured_levels() {
+        let config = BTreeMap::from([
+            (LintRule::OrphanNote, LintLevel::Warning),
+            (LintRule::DeadLink, LintLevel::Off),
+        ]);
+        assert_eq!(LintRule::OrphanNote.level(&config), LintLevel::Warning);
+        assert_eq!(LintRule::DeadLink.level(&config), LintLevel::Off);
+        assert_eq!(LintRule::EmptyNote.level(&config), LintLevel::Info);
+        assert_eq!(LintRule::OrphanNote.level(&BTreeMap::new()), LintLevel::Off);
+
+        let config: BTreeMap<LintRule, LintLevel> =
+            toml::from_str("orphan-note = \"error\"\ndead-link = \"off\"").unwrap();
+        assert_eq!(LintRule::OrphanNote.level(&config), LintLevel::Error);
+        assert_eq!(LintRule::DeadLink.level(&config), LintLevel::Off);
+    }
+
+    #[test]
+    fn dead_link_rules() {
+        let note = Target::Note(1, PathBuf::from("/v/a.md"));
+        let rule = |link: &Link, target: &Target| LintRule::dead_link(link, target).0;
+        assert_eq!(
+            rule(&link(Some("H"), None), &note),
+            LintRule::MissingHeading
+        );
+        assert_eq!(rule(&link(None, Some("b")), &note), LintRule::MissingBlock);
+        assert_eq!(rule(&link(None, None), &note), LintRule::DeadLink);
+        assert_eq!(
+            rule(&link(Some("H"), None), &Target::Missing),
+            LintRule::DeadLink
+        );
+        let ambiguous = Target::Ambiguous(vec![]);
+        assert_eq!(rule(&link(None, Some("b")), &ambiguous), LintRule::DeadLink);
+        assert_eq!(
+            LintRule::dead_link(&link(Some("H"), None), &note).1,
+            "no heading `H` in /v/a.md"
+        );
+    }
+
+    #[test]
+    fn doctor_fail_level() {
+        let info = [lint(LintLevel::Info)];
+        let warning = [lint(LintLevel::Info), lint(LintLevel::Warning)];
+        let error = [lint(LintLevel::Error)];
+        assert!(!LintLevel::fails(&[], true));
+        assert!(!LintLevel::fails(&info, true));
+        assert!(!LintLevel::fails(&warning, false));
+        assert!(LintLevel::fails(&warning, true));
+        assert!(LintLevel::fails(&error, false));
+        assert!(LintLevel::fails(&error, true));
+    }
+}
@@ -1,71 +1,510 @@
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-use serde::Deserialize;
+use chrono::NaiveDate;
+use linkify::LinkFinder;
+use percent_encoding::percent_decode_str;
+use pulldown_cmark::{Event, Parser, Tag};
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
 
-use crate::error::Result;
+use crate::error::{Result, SovError};
 
 pub struct SovNote {
     pub filename: String,
     pub path: PathBuf,
     pub yaml: YamlMetadata,
     pub links: Vec<Link>,
+    pub headings: Vec<Heading>,
+    pub blocks: Vec<Block>,
+    /// `#tags` found in the note content, see [`YamlMetadata::tags`] for the
+    /// frontmatter tags
+    pub inline_tags: Vec<InlineTag>,
+    pub properties: Vec<Property>,
+    pub content: String,
+    pub stat: NoteStat,
+    /// The frontmatter could not be parsed, the note has no metadata
+    pub frontmatter_error: Option<String>,
+    /// Number of words outside of the frontmatter
+    pub word_count: usize,
 }
 
-#[derive(Debug)]
+/// File information used to detect modified notes during indexing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoteStat {
+    /// Modification time in nanoseconds since the epoch
+    pub mtime: i64,
+    pub size: u64,
+    /// Hex-encoded blake3 hash of the note content
+    pub hash: String,
+}
+
+#[derive(Debug, Serialize)]
 pub struct Link {
+    pub kind: LinkKind,
     pub value: String,
     pub alias: Option<String>,
     pub header: Option<String>,
+    /// Block reference, e.g. `abc123` for `[[Note#^abc123]]`
+    pub block: Option<String>,
+    /// Decoded relative path of markdown links, e.g. `../Other Note.md`
+    pub path: Option<String>,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkKind {
+    /// `[[Note]]`
+    Link,
+    /// `![[Note]]`
+    Embed,
+    /// `![[image.png]]`
+    ImageEmbed,
+    /// `[text](relative/path/to/Note.md)`
+    Markdown,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Heading {
+    pub level: u8,
+    pub text: String,
+    /// GitHub-style anchor of the heading (e.g. `my-heading`)
+    pub slug: String,
+    /// 0-based line of the heading
+    pub start: usize,
+    /// 0-based last line of the heading section, sub-headings included
+    pub end: usize,
+}
+
+/// A block that can be referenced with `[[Note#^id]]`, marked by a trailing
+/// `^id` in the note
+#[derive(Debug, Clone, Serialize)]
+pub struct Block {
+    pub id: String,
+    /// 0-based line of the `^id` marker
+    pub line: usize,
+}
+
+/// A `#tag` or `#nested/tag` written in the note content
+#[derive(Debug, Clone)]
+pub struct InlineTag {
+    /// Tag name, without the `#`
+    pub name: String,
+    /// Char offset of the `#`
     pub start: usize,
+    /// Char offset of the last char of the tag
     pub end: usize,
 }
 
-// TODO: should I make this mandatory?
-#[derive(Debug, Deserialize)]
+/// A reference to a note, optionally to one of its lines
+#[derive(Debug, Clone, Serialize)]
+pub struct NoteRef {
+    pub name: String,
+    pub path: PathBuf,
+    /// 1-based line
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+}
+
+/// Notes sharing the same filename, links to them must be path-qualified
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateNotes {
+    pub filename: String,
+    pub paths: Vec<PathBuf>,
+}
+
+impl DuplicateNotes {
+    /// Groups `(filename, path)` rows sorted by filename, filenames of a single
+    /// note are left out
+    pub(crate) fn group(notes: Vec<(String, PathBuf)>) -> Vec<Self> {
+        let mut duplicates: Vec<DuplicateNotes> = Vec::new();
+        for (filename, path) in notes {
+            match duplicates.last_mut() {
+                Some(d) if d.filename == filename => d.paths.push(path),
+                _ => duplicates.push(DuplicateNotes {
+                    filename,
+                    paths: vec![path],
+                }),
+            }
+        }
+        duplicates.retain(|d| d.paths.len() > 1);
+        duplicates
+    }
+}
+
+/// A link along with the path of the note containing it
+#[derive(Debug, Serialize)]
+pub struct LinkRef {
+    pub path: PathBuf,
+    pub link: Link,
+}
+
+/// A note matching a full-text search query
+#[derive(Debug, Serialize)]
+pub struct TextMatch {
+    pub path: PathBuf,
+    /// 1-based line of the first match in the note
+    pub line: usize,
+    /// Excerpt of the matching text, matched terms are wrapped in `**`
+    pub snippet: String,
+}
+
+/// The frontmatter metadata, `aliases` and `tags` may be a list, a single
+/// string or missing
+#[derive(Debug, Default, Deserialize)]
 pub struct YamlMetadata {
+    #[serde(default, deserialize_with = "YamlMetadata::deserialize_aliases")]
     pub aliases: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "YamlMetadata::deserialize_tags")]
     pub tags: Vec<String>,
+    /// All the other keys
+    #[serde(flatten)]
+    pub properties: BTreeMap<String, serde_yaml::Value>,
+}
+
+/// A plain-text mention of a note in another note, see
+/// [`crate::Sov::resolve_unlinked_mentions`]
+#[derive(Debug, Clone, Serialize)]
+pub struct Mention {
+    pub path: PathBuf,
+    /// 1-based line
+    pub line: usize,
+    /// Char offsets of the mention, `end` is inclusive
+    pub start: usize,
+    pub end: usize,
+    /// The mention as written in the note
+    pub text: String,
+    /// The wiki-link replacing the mention, e.g. `[[My Note|my note]]`
+    pub link: String,
+}
+
+/// A note that could not be fully indexed, e.g. because of an invalid
+/// frontmatter
+#[derive(Debug, Clone, Serialize)]
+pub struct NoteError {
+    pub path: PathBuf,
+    pub error: String,
+}
+
+/// A frontmatter `key: value` pair, lists are split into one property per item
+/// and nested keys are joined with a `.` (e.g. `project.status`)
+#[derive(Debug, Clone)]
+pub struct Property {
+    pub key: String,
+    /// `None` for empty values
+    pub value: Option<String>,
+    pub kind: PropertyKind,
+}
+
+/// A frontmatter property key with the number of notes using it
+#[derive(Debug, Clone, Serialize)]
+pub struct PropertyCount {
+    pub key: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropertyKind {
+    String,
+    Number,
+    Bool,
+    /// `YYYY-MM-DD`
+    Date,
+    Null,
 }
 
 impl SovNote {
     pub fn new(path: PathBuf, filename: String) -> Result<Self> {
+        // Read the metadata first so that a concurrent write is picked up on the
+        // next index
+        let metadata = std::fs::metadata(&path)?;
         let content = std::fs::read_to_string(&path)?;
-        let yaml = SovNote::parse_yaml(&content)?;
+        let stat = NoteStat {
+            mtime: NoteStat::mtime(&metadata),
+            size: metadata.size(),
+            hash: NoteStat::hash(&content),
+        };
+        // The rest of the note is still indexed without its metadata
+        let (yaml, frontmatter_error) = match SovNote::parse_yaml(&content) {
+            Ok(yaml) => (yaml, None),
+            Err(e) => (YamlMetadata::default(), Some(e.to_string())),
+        };
         let links = SovNote::parse_links(&content)?;
+        let headings = SovNote::parse_headings(&content);
+        let blocks = SovNote::parse_blocks(&content);
+        let inline_tags = SovNote::parse_tags(&content);
+        let properties = SovNote::parse_properties(&yaml);
+        let word_count = SovNote::split_frontmatter(&content)
+            .1
+            .split_whitespace()
+            .count();
 
         Ok(Self {
             filename,
             path,
             yaml,
             links,
+            headings,
+            blocks,
+            inline_tags,
+            properties,
+            content,
+            stat,
+            frontmatter_error,
+            word_count,
         })
     }
 
+    /// Whether the file at `path` is a markdown note
+    pub fn is_note(path: &Path) -> bool {
+        path.extension().map(|s| s == "md").unwrap_or(false)
+    }
+
+    /// The note filename, used as the wiki-link target
+    pub fn filename(path: &Path) -> Option<String> {
+        Some(path.file_stem()?.to_str()?.to_string())
+    }
+
+    /// The YAML frontmatter, only if the note starts with a `---` line, up to
+    /// the next `---` (or `...`) line
+    pub fn frontmatter(s: &str) -> Option<&str> {
+        Self::split_frontmatter(s).0
+    }
+
+    /// Splits the note into its frontmatter, see [`SovNote::frontmatter`], and
+    /// its body
+    pub fn split_frontmatter(s: &str) -> (Option<&str>, &str) {
+        let s = s.strip_prefix('\u{feff}').unwrap_or(s);
+        let mut lines = s.split_inclusive('\n');
+        if lines.next().map(str::trim_end) != Some("---") {
+            return (None, s);
+        }
+        let start = s.find('\n').map_or(s.len(), |i| i + 1);
+        let mut end = start;
+        for line in lines {
+            if matches!(line.trim_end(), "---" | "...") {
+                return (Some(&s[start..end]), &s[end + line.len()..]);
+            }
+            end += line.len();
+        }
+        (None, s)
+    }
+
+    /// Number of lines of the frontmatter, its `---` lines included
+    pub fn frontmatter_lines(s: &str) -> usize {
+        Self::frontmatter(s).map_or(0, |f| f.split_inclusive('\n').count() + 2)
+    }
+
     pub fn parse_yaml(s: &str) -> Result<YamlMetadata> {
-        let yaml: YamlMetadata = match s
-            .split("---")
-            .nth(1)
-            .and_then(|s| s.split("---").nth(0))
-        {
-            Some(metadata) => serde_yaml::from_str(metadata)?,
-            None => YamlMetadata {
-                aliases: None,
-                tags: Vec::new(),
-            },
+        let Some(frontmatter) = Self::frontmatter(s) else {
+            return Ok(YamlMetadata::default());
         };
-        Ok(yaml)
+        // e.g. an empty frontmatter or a single scalar
+        let value: serde_yaml::Value = serde_yaml::from_str(frontmatter)?;
+        if !value.is_mapping() {
+            return Ok(YamlMetadata::default());
+        }
+        Ok(serde_yaml::from_value(value)?)
+    }
+
+    /// Flattens the frontmatter properties, see [`Property`]
+    pub fn parse_properties(yaml: &YamlMetadata) -> Vec<Property> {
+        let mut properties = Vec::new();
+        for (key, value) in &yaml.properties {
+            Property::flatten(key.clone(), value, &mut properties);
+        }
+        properties
+    }
+
+    /// Parses ATX headings (`# Heading`), skipping the YAML metadata and code
+    /// blocks
+    pub fn parse_headings(s: &str) -> Vec<Heading> {
+        let mut headings: Vec<Heading> = Vec::new();
+        let mut in_code = false;
+        let mut last_line = 0;
+        let yaml_lines = Self::frontmatter_lines(s);
+
+        for (i, line) in s.lines().enumerate() {
+            last_line = i;
+            let trimmed = line.trim_start();
+            if i < yaml_lines {
+                continue;
+            }
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                in_code = !in_code;
+                continue;
+            }
+            if in_code {
+                continue;
+            }
+
+            let level = trimmed.chars().take_while(|c| *c == '#').count();
+            if level == 0 || level > 6 {
+                continue;
+            }
+            let rest = &trimmed[level..];
+            if !rest.is_empty() && !rest.starts_with([' ', '\t']) {
+                // e.g. a #tag
+                continue;
+            }
+            // Closing sequence, e.g. `## Heading ##`
+            let text = rest.trim().trim_end_matches('#').trim_end().to_string();
+            let level = level as u8;
+
+            // Close the sections of the previous headings with the same or a
+            // higher level
+            for heading in headings.iter_mut().rev() {
+                if heading.end != usize::MAX {
+                    continue;
+                }
+                if heading.level >= level {
+                    heading.end = i - 1;
+                }
+            }
+            headings.push(Heading {
+                level,
+                slug: Heading::slugify(&text),
+                text,
+                start: i,
+                end: usize::MAX,
+            });
+        }
+
+        for heading in headings.iter_mut() {
+            if heading.end == usize::MAX {
+                heading.end = last_line;
+            }
+        }
+        headings
+    }
+
+    /// Parses the `^id` block markers, skipping code blocks
+    pub fn parse_blocks(s: &str) -> Vec<Block> {
+        let mut blocks: Vec<Block> = Vec::new();
+        let mut in_code = false;
+        for (i, line) in s.lines().enumerate() {
+            let trimmed = line.trim_start();
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                in_code = !in_code;
+                continue;
+            }
+            if in_code {
+                continue;
+            }
+            let Some(id) = Block::parse_id(line) else {
+                continue;
+            };
+            // The first marker wins, as in Obsidian
+            if blocks.iter().any(|b| b.id == id) {
+                continue;
+            }
+            blocks.push(Block {
+                id: id.to_string(),
+                line: i,
+            });
+        }
+        blocks
+    }
+
+    /// Parses the inline `#tags`, skipping the YAML metadata and code.
+    ///
+    /// A tag starts with a `#` at the start of a line or after a whitespace, so
+    /// heading markers, URL fragments and `[[Note#Header]]` links are not tags.
+    pub fn parse_tags(s: &str) -> Vec<InlineTag> {
+        let mut tags = Vec::new();
+        let mut in_code = false;
+        let mut line_off = 0;
+        let yaml_lines = Self::frontmatter_lines(s);
+
+        for (i, line) in s.split_inclusive('\n').enumerate() {
+            let off = line_off;
+            line_off += line.chars().count();
+            let trimmed = line.trim();
+            if i < yaml_lines {
+                continue;
+            }
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                in_code = !in_code;
+                continue;
+            }
+            if in_code {
+                continue;
+            }
+
+            let mut in_code_span = false;
+            let mut prev = None;
+            let mut chars = line.chars().enumerate().peekable();
+            while let Some((j, c)) = chars.next() {
+                let is_start = prev.is_none_or(char::is_whitespace);
+                prev = Some(c);
+                match c {
+                    '`' => in_code_span = !in_code_span,
+                    '#' if is_start && !in_code_span => {
+                        let mut name = String::new();
+                        while let Some((_, c)) = chars.next_if(|(_, c)| InlineTag::is_tag_char(*c))
+                        {
+                            name.push(c);
+                            prev = Some(c);
+                        }
+                        let name = name.trim_end_matches('/');
+                        // e.g. `#123` is not a tag
+                        if name.chars().all(|c| c.is_ascii_digit() || c == '/') {
+                            continue;
+                        }
+                        let start = off + j;
+                        tags.push(InlineTag {
+                            name: name.to_string(),
+                            start,
+                            end: start + name.chars().count(),
+                        });
+                    }
+                    _ => (),
+                }
+            }
+        }
+        tags
     }
 
+    /// Parses the wiki-links and relative markdown links, skipping code blocks,
+    /// inline code and HTML comments
     pub fn parse_links(s: &str) -> Result<Vec<Link>> {
         let mut chars = s.chars().peekable().enumerate();
         let mut links = Vec::new();
         let mut is_escaped = false;
+        let mut is_bang = false;
+        let mut ignored = Self::parse_ignored_ranges(s).into_iter().peekable();
 
         while let Some((i, c)) = chars.next() {
+            while ignored.next_if(|r| r.end <= i).is_some() {}
+            if ignored.peek().is_some_and(|r| r.contains(&i)) {
+                is_escaped = false;
+                is_bang = false;
+                continue;
+            }
+            let is_embed = std::mem::take(&mut is_bang);
             match c {
                 '\\' => is_escaped = true,
+                '!' if !is_escaped => is_bang = true,
                 '[' if !is_escaped => {
-                    let start_off = i;
+                    // The `!` of embeds is part of the link
+                    let start_off = if is_embed { i - 1 } else { i };
+                    if chars.clone().next().is_some_and(|(_, c)| c != '[') {
+                        // Images are not notes, only plain markdown links are parsed
+                        if is_embed {
+                            continue;
+                        }
+                        let mut lookahead = chars.clone();
+                        if let Some(link) = Self::parse_markdown_link(&mut lookahead, start_off) {
+                            links.push(link);
+                            chars = lookahead;
+                        }
+                        continue;
+                    }
                     if let Some((_, '[')) = chars.next() {
                         let s: String = chars
                             .by_ref()
@@ -84,11 +523,24 @@ impl SovNote {
                             Some((link, header)) => (link.to_string(), Some(header.to_string())),
                             None => (rest.to_string(), None),
                         };
+                        let (header, block) = match header {
+                            Some(h) if h.starts_with('^') => (None, Some(h[1..].to_string())),
+                            h => (h, None),
+                        };
+
+                        let kind = match is_embed {
+                            true if LinkKind::is_image(&link) => LinkKind::ImageEmbed,
+                            true => LinkKind::Embed,
+                            false => LinkKind::Link,
+                        };
 
                         links.push(Link {
+                            kind,
                             value: link,
                             alias,
                             header,
+                            block,
+                            path: None,
                             start: start_off,
                             end: end_off,
                         });
@@ -99,20 +551,747 @@ impl SovNote {
         }
         Ok(links)
     }
+
+    /// Parses the case-insensitive mentions of `names` as whole words, outside
+    /// of the frontmatter, links, URLs, tags, code and HTML comments.
+    ///
+    /// Returns the char offsets of the mentions, `end` being inclusive. The
+    /// longest name wins when several names match at the same offset.
+    pub fn parse_mentions(s: &str, names: &[String]) -> Vec<(usize, usize)> {
+        let chars: Vec<char> = s.chars().collect();
+        let mut skipped = Self::parse_ignored_ranges(s);
+        skipped.extend(Self::parse_url_ranges(s));
+        let links = Self::parse_links(s).unwrap_or_default();
+        skipped.extend(links.iter().map(|l| l.start..l.end + 1));
+        let tags = Self::parse_tags(s);
+        skipped.extend(tags.iter().map(|t| t.start..t.end + 1));
+        if let (Some(_), body) = Self::split_frontmatter(s) {
+            skipped.push(0..s[..s.len() - body.len()].chars().count());
+        }
+
+        // Only the names starting like the word are compared
+        let lowercase = |c: char| c.to_lowercase().next().unwrap_or(c);
+        let mut by_first: BTreeMap<char, Vec<Vec<char>>> = BTreeMap::new();
+        for name in names.iter().filter(|n| !n.trim().is_empty()) {
+            let name: Vec<char> = name.chars().collect();
+            by_first.entry(lowercase(name[0])).or_default().push(name);
+        }
+        let eq = |a: char, b: char| a == b || a.to_lowercase().eq(b.to_lowercase());
+        let matches_at = |i: usize, name: &[char]| {
+            let end = i + name.len();
+            end <= chars.len()
+                && chars[i..end].iter().zip(name).all(|(a, b)| eq(*a, *b))
+                && chars.get(end).is_none_or(|c| !c.is_alphanumeric())
+        };
+
+        let mut mentions = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            // Any other char separates words, e.g. `/`, `.` or `#`
+            let is_start = i == 0 || !chars[i - 1].is_alphanumeric();
+            let len = match is_start {
+                true => by_first.get(&lowercase(chars[i])).and_then(|names| {
+                    names
+                        .iter()
+                        .filter(|name| matches_at(i, name))
+                        .map(|name| name.len())
+                        .max()
+                }),
+                false => None,
+            };
+            match len {
+                Some(len) if !skipped.iter().any(|r| r.start < i + len && i < r.end) => {
+                    mentions.push((i, i + len - 1));
+                    i += len;
+                }
+                _ => i += 1,
+            }
+        }
+        mentions
+    }
+
+    /// Whether a `^id` block marker can be appended to the 0-based `line`: the
+    /// line has content and is neither in the frontmatter, nor in a code block
+    /// or an HTML comment
+    pub fn accepts_block_id(s: &str, line: usize) -> bool {
+        let text = Rope::from_str(s);
+        let Some(line_str) = text.get_line(line) else {
+            return false;
+        };
+        let line_str = line_str.to_string();
+        let line_str = line_str.trim_end_matches(['\n', '\r']);
+        if line_str.trim().is_empty() || line < Self::frontmatter_lines(s) {
+            return false;
+        }
+        let start = text.line_to_char(line);
+        // The marker would be appended inside an ignored range, or to the last
+        // line of a multi-line one (e.g. the closing fence of a code block)
+        let end = start + line_str.chars().count();
+        !Self::parse_ignored_ranges(s)
+            .iter()
+            .any(|r| r.start <= end && (end < r.end || (end == r.end && r.start < start)))
+    }
+
+    /// The 0-based line where the fenced code block, HTML comment or
+    /// frontmatter containing the 0-based `line` starts, or `line` itself if it
+    /// is in none of them.
+    ///
+    /// The lines are only scanned for fences and comment markers, so that a
+    /// part of a note can be parsed without parsing the whole note.
+    pub fn enclosing_block_line(text: &Rope, line: usize) -> usize {
+        let mut open: Option<(usize, String)> = None;
+        for (i, l) in text.lines().take(line).enumerate() {
+            let l = l.to_string();
+            let t = l.trim();
+            match &open {
+                Some((_, end)) if end == "---" => {
+                    if t == "---" || t == "..." {
+                        open = None;
+                    }
+                }
+                Some((_, end)) if end == "-->" => {
+                    if t.contains("-->") {
+                        open = None;
+                    }
+                }
+                // A closing fence is at least as long as the opening one
+                Some((_, fence)) => {
+                    if t.starts_with(fence.as_str()) && t.chars().all(|c| fence.starts_with(c)) {
+                        open = None;
+                    }
+                }
+                None if i == 0 && t == "---" => open = Some((i, "---".into())),
+                None if t.starts_with("```") || t.starts_with("~~~") => {
+                    let c = t.chars().next().unwrap_or_default();
+                    open = Some((i, t.chars().take_while(|x| *x == c).collect()));
+                }
+                None => {
+                    if let Some(pos) = t.find("<!--") {
+                        if !t[pos..].contains("-->") {
+                            open = Some((i, "-->".into()));
+                        }
+                    }
+                }
+            }
+        }
+        open.map_or(line, |(i, _)| i)
+    }
+
+    /// Char ranges of the code blocks, inline code spans and HTML comments,
+    /// sorted by start
+    pub(crate) fn parse_ignored_ranges(s: &str) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        for (event, range) in Parser::new(s).into_offset_iter() {
+            let is_ignored = match event {
+                Event::Start(Tag::CodeBlock(_)) | Event::Code(_) => true,
+                Event::InlineHtml(_) => s[range.clone()].starts_with("<!--"),
+                // An HTML block starting with a comment runs to the end of the
+                // line closing it, only the comments are skipped and other
+                // HTML may wrap markdown content
+                Event::Start(Tag::HtmlBlock) => {
+                    let mut start = range.start;
+                    while let Some(i) = s[start..range.end].find("<!--") {
+                        let comment = start + i;
+                        start = match s[comment..range.end].find("-->") {
+                            Some(j) => comment + j + 3,
+                            None => range.end,
+                        };
+                        if ranges.last().is_none_or(|r| r.end <= comment) {
+                            ranges.push(comment..start);
+                        }
+                    }
+                    false
+                }
+                _ => false,
+            };
+            // Blocks nested in an ignored block, e.g. a code block in a comment
+            if is_ignored && ranges.last().is_none_or(|r| r.end <= range.start) {
+                ranges.push(range);
+            }
+        }
+        Self::to_char_ranges(s, ranges)
+    }
+
+    /// Char ranges of the markdown links and images, autolinks and bare URLs,
+    /// sorted by start
+    fn parse_url_ranges(s: &str) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        for (event, range) in Parser::new(s).into_offset_iter() {
+            if let Event::Start(Tag::Link { .. } | Tag::Image { .. }) = event {
+                ranges.push(range);
+            }
+        }
+        ranges.extend(LinkFinder::new().links(s).map(|l| l.start()..l.end()));
+        ranges.sort_by_key(|r| r.start);
+
+        // e.g. an image in a link, or the URL of an autolink
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start < last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        Self::to_char_ranges(s, merged)
+    }
+
+    /// Converts byte ranges sorted by start and not overlapping into char
+    /// ranges
+    fn to_char_ranges(s: &str, ranges: Vec<Range<usize>>) -> Vec<Range<usize>> {
+        let mut char_ranges = Vec::with_capacity(ranges.len());
+        let mut chars = 0;
+        let mut bytes = 0;
+        let mut to_char = |byte: usize| {
+            chars += s[bytes..byte].chars().count();
+            bytes = byte;
+            chars
+        };
+        for range in ranges {
+            let start = to_char(range.start);
+            let end = to_char(range.end);
+            char_ranges.push(start..end);
+        }
+        char_ranges
+    }
+
+    /// Parses a `[text](relative/path/to/note.md#anchor)` link, `chars` starts
+    /// right after the opening `[`
+    fn parse_markdown_link<I>(chars: &mut I, start: usize) -> Option<Link>
+    where
+        I: Iterator<Item = (usize, char)>,
+    {
+        let text: String = chars
+            .by_ref()
+            .take_while(|(_, c)| *c != ']' && *c != '\n')
+            .map(|(_, c)| c)
+            .collect();
+        let Some((_, '(')) = chars.next() else {
+            return None;
+        };
+        let mut target = String::new();
+        let end = loop {
+            match chars.next()? {
+                (_, '\n') => return None,
+                (end, ')') => break end,
+                (_, c) => target.push(c),
+            }
+        };
+
+        let target = target.trim();
+        let target = target
+            .strip_prefix('<')
+            .and_then(|t| t.strip_suffix('>'))
+            .unwrap_or(target);
+        // Skip URLs
+        if target.contains("://") || target.starts_with("mailto:") {
+            return None;
+        }
+        let (path, header) = match target.split_once('#') {
+            Some((path, header)) => (path, Some(header)),
+            None => (target, None),
+        };
+        let path = percent_decode_str(path).decode_utf8().ok()?.to_string();
+        if !SovNote::is_note(Path::new(&path)) {
+            return None;
+        }
+        let value = SovNote::filename(Path::new(&path))?;
+        let header = match header {
+            Some(h) => Some(percent_decode_str(h).decode_utf8().ok()?.to_string()),
+            None => None,
+        };
+        let (header, block) = match header {
+            Some(h) if h.starts_with('^') => (None, Some(h[1..].to_string())),
+            h => (h, None),
+        };
+
+        Some(Link {
+            kind: LinkKind::Markdown,
+            value,
+            alias: Some(text),
+            header,
+            block,
+            path: Some(path),
+            start,
+            end,
+        })
+    }
+}
+
+impl YamlMetadata {
+    /// Tags may be a list or a single string of tags separated by commas or
+    /// spaces, e.g. `tags: project, work`
+    fn deserialize_tags<'de, D>(deserializer: D) -> std::result::Result<Vec<String>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let tags = Self::string_list(serde_yaml::Value::deserialize(deserializer)?)
+            .iter()
+            .flat_map(|t| t.split([',', ' ']))
+            .map(|t| t.trim().trim_start_matches('#'))
+            .filter(|t| !t.is_empty())
+            .map(str::to_string)
+            .collect();
+        Ok(tags)
+    }
+
+    /// Aliases may be a list or a single alias
+    fn deserialize_aliases<'de, D>(
+        deserializer: D,
+    ) -> std::result::Result<Option<Vec<String>>, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let aliases = Self::string_list(serde_yaml::Value::deserialize(deserializer)?);
+        Ok(Some(aliases).filter(|a| !a.is_empty()))
+    }
+
+    /// The scalars of a list or a single scalar, e.g. `2024` is read as a
+    /// string
+    fn string_list(value: serde_yaml::Value) -> Vec<String> {
+        let scalar = |v: serde_yaml::Value| match v {
+            serde_yaml::Value::String(s) => Some(s),
+            serde_yaml::Value::Number(n) => Some(n.to_string()),
+            serde_yaml::Value::Bool(b) => Some(b.to_string()),
+            _ => None,
+        };
+        match value {
+            serde_yaml::Value::Sequence(items) => items.into_iter().filter_map(scalar).collect(),
+            v => scalar(v).into_iter().collect(),
+        }
+    }
+}
+
+impl Property {
+    fn flatten(key: String, value: &serde_yaml::Value, properties: &mut Vec<Property>) {
+        use serde_yaml::Value;
+
+        let (value, kind) = match value {
+            Value::Null => (None, PropertyKind::Null),
+            Value::Bool(b) => (Some(b.to_string()), PropertyKind::Bool),
+            Value::Number(n) => (Some(n.to_string()), PropertyKind::Number),
+            Value::String(s) if NaiveDate::parse_from_str(s, "%Y-%m-%d").is_ok() => {
+                (Some(s.clone()), PropertyKind::Date)
+            }
+            Value::String(s) => (Some(s.clone()), PropertyKind::String),
+            Value::Sequence(items) => {
+                for item in items {
+                    Self::flatten(key.clone(), item, properties);
+                }
+                return;
+            }
+            Value::Mapping(mapping) => {
+                for (k, v) in mapping {
+                    let Some(k) = k.as_str() else {
+                        continue;
+                    };
+                    Self::flatten(format!("{}.{}", key, k), v, properties);
+                }
+                return;
+            }
+            Value::Tagged(tagged) => return Self::flatten(key, &tagged.value, properties),
+        };
+        properties.push(Property { key, value, kind });
+    }
+}
+
+impl PropertyKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PropertyKind::String => "string",
+            PropertyKind::Number => "number",
+            PropertyKind::Bool => "bool",
+            PropertyKind::Date => "date",
+            PropertyKind::Null => "null",
+        }
+    }
+}
+
+impl InlineTag {
+    pub fn is_tag_char(c: char) -> bool {
+        c.is_alphanumeric() || c == '_' || c == '-' || c == '/'
+    }
+}
+
+impl LinkKind {
+    const IMAGE_EXTENSIONS: [&'static str; 8] =
+        ["png", "jpg", "jpeg", "gif", "bmp", "svg", "webp", "avif"];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkKind::Link => "link",
+            LinkKind::Embed => "embed",
+            LinkKind::ImageEmbed => "image_embed",
+            LinkKind::Markdown => "markdown",
+        }
+    }
+
+    fn is_image(link_value: &str) -> bool {
+        Path::new(link_value)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| {
+                Self::IMAGE_EXTENSIONS
+                    .iter()
+                    .any(|e| e.eq_ignore_ascii_case(ext))
+            })
+    }
+}
+
+impl FromStr for LinkKind {
+    type Err = SovError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "link" => Ok(LinkKind::Link),
+            "embed" => Ok(LinkKind::Embed),
+            "image_embed" => Ok(LinkKind::ImageEmbed),
+            "markdown" => Ok(LinkKind::Markdown),
+            _ => Err(SovError::InvalidLinkKind(s.to_string())),
+        }
+    }
+}
+
+impl Block {
+    /// Returns the id of the `^id` marker ending `line`, if any
+    pub fn parse_id(line: &str) -> Option<&str> {
+        let line = line.trim_end();
+        let (before, id) = line.rsplit_once('^')?;
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return None;
+        }
+        // The marker is either alone on its line or separated by a space
+        if !before.is_empty() && !before.ends_with([' ', '\t']) {
+            return None;
+        }
+        Some(id)
+    }
+
+    /// Generates a new random-looking block id for the given note line,
+    /// distinct from the `existing` blocks of the note
+    pub fn new_id(path: &Path, line: usize, existing: &[Block]) -> String {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut attempt = 0;
+        loop {
+            let seed = format!("{}:{}:{}:{}", path.display(), line, now.as_nanos(), attempt);
+            let id = blake3::hash(seed.as_bytes()).to_hex()[..6].to_string();
+            if !existing.iter().any(|b| b.id == id) {
+                return id;
+            }
+            attempt += 1;
+        }
+    }
+}
+
+impl Heading {
+    pub fn slugify(text: &str) -> String {
+        text.trim()
+            .to_lowercase()
+            .chars()
+            .filter_map(|c| match c {
+                ' ' => Some('-'),
+                c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl NoteStat {
+    pub fn mtime(metadata: &std::fs::Metadata) -> i64 {
+        metadata.mtime() * 1_000_000_000 + metadata.mtime_nsec()
+    }
+
+    pub fn hash(content: &str) -> String {
+        blake3::hash(content.as_bytes()).to_hex().to_string()
+    }
+}
+
+impl Link {
+    /// Points the link to another note in the same directory
+    pub fn rename_target(&mut self, new_filename: &str) {
+        // Keep the path qualification, e.g. `projects/sov/index`
+        self.value = match self.value.rsplit_once('/') {
+            Some((dir, _)) => format!("{}/{}", dir, new_filename),
+            None => new_filename.to_string(),
+        };
+        if let Some(path) = &self.path {
+            let new_path = Path::new(path)
+                .with_file_name(new_filename)
+                .with_extension("md");
+            self.path = Some(new_path.to_string_lossy().to_string());
+        }
+    }
+
+    /// Encodes the characters that would break a markdown link destination
+    fn encode_path(path: &str) -> String {
+        path.replace('%', "%25")
+            .replace(' ', "%20")
+            .replace('(', "%28")
+            .replace(')', "%29")
+    }
+}
+
+impl NoteRef {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            name: SovNote::filename(&path).unwrap_or_default(),
+            path,
+            line: None,
+        }
+    }
+
+    /// `line` is 0-based
+    pub fn at_line(mut self, line: usize) -> Self {
+        self.line = Some(line + 1);
+        self
+    }
+}
+
+impl From<PathBuf> for NoteRef {
+    fn from(path: PathBuf) -> Self {
+        Self::new(path)
+    }
+}
+
+impl std::fmt::Display for NoteRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<(PathBuf, Link)> for LinkRef {
+    fn from((path, link): (PathBuf, Link)) -> Self {
+        Self { path, link }
+    }
 }
 
-impl ToString for Link {
-    fn to_string(&self) -> String {
-        let mut s = format!("[[{}", self.value);
-        match &self.header {
-            Some(header) => s.push_str(&format!("#{}", header)),
-            None => (),
+impl std::fmt::Display for Mention {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}: {} -> {}",
+            self.path.display(),
+            self.line,
+            self.text,
+            self.link
+        )
+    }
+}
+
+impl std::fmt::Display for LinkRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.link)
+    }
+}
+
+impl std::fmt::Display for Link {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.kind == LinkKind::Markdown {
+            let path = self.path.as_deref().unwrap_or(&self.value);
+            write!(
+                f,
+                "[{}]({}",
+                self.alias.as_deref().unwrap_or_default(),
+                Self::encode_path(path)
+            )?;
+            if let Some(header) = &self.header {
+                write!(f, "#{}", Self::encode_path(header))?;
+            }
+            if let Some(block) = &self.block {
+                write!(f, "#^{}", block)?;
+            }
+            return write!(f, ")");
+        }
+
+        if self.kind != LinkKind::Link {
+            write!(f, "!")?;
+        }
+        write!(f, "[[{}", self.value)?;
+        if let Some(header) = &self.header {
+            write!(f, "#{}", header)?;
+        }
+        if let Some(block) = &self.block {
+            write!(f, "#^{}", block)?;
         }
-        match &self.alias {
-            Some(alias) => s.push_str(&format!("|{}", alias)),
-            None => (),
+        if let Some(alias) = &self.alias {
+            write!(f, "|{}", alias)?;
         }
-        s.push_str("]]");
-        s
+        write!(f, "]]")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_ids_are_not_appended_to_frontmatter_or_code() {
+        let s = "---\ntitle: x\n---\ntext\n\n```\ncode\n```\nafter `code`\n";
+        let accepted: Vec<usize> = (0..10)
+            .filter(|line| SovNote::accepts_block_id(s, *line))
+            .collect();
+        assert_eq!(accepted, vec![3, 8]);
+    }
+
+    #[test]
+    fn frontmatter_closed_by_dots() {
+        let s = "---\ntags: a\n...\n# Title\ntext #tag\n";
+        assert_eq!(SovNote::frontmatter(s), Some("tags: a\n"));
+        assert_eq!(SovNote::frontmatter_lines(s), 3);
+        let headings = SovNote::parse_headings(s);
+        assert_eq!(headings.len(), 1);
+        assert_eq!((headings[0].text.as_str(), headings[0].start), ("Title", 3));
+        let tags = SovNote::parse_tags(s);
+        assert_eq!(tags.len(), 1);
+        assert_eq!((tags[0].name.as_str(), tags[0].start), ("tag", 29));
+    }
+
+    #[test]
+    fn leading_thematic_break_is_not_frontmatter() {
+        let s = "---\n# One\n#tag\n## Two\n";
+        assert_eq!(SovNote::frontmatter(s), None);
+        assert_eq!(SovNote::frontmatter_lines(s), 0);
+        let headings: Vec<String> = SovNote::parse_headings(s)
+            .into_iter()
+            .map(|h| h.text)
+            .collect();
+        assert_eq!(headings, vec!["One", "Two"]);
+        let tags: Vec<String> = SovNote::parse_tags(s).into_iter().map(|t| t.name).collect();
+        assert_eq!(tags, vec!["tag"]);
+    }
+
+    fn mentions(s: &str, names: &[&str]) -> Vec<String> {
+        let names: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+        let chars: Vec<char> = s.chars().collect();
+        SovNote::parse_mentions(s, &names)
+            .into_iter()
+            .map(|(start, end)| chars[start..=end].iter().collect())
+            .collect()
+    }
+
+    #[test]
+    fn mentions_are_whole_words() {
+        let s = "Sov and sov. docs/sov sovereign, Sov Core";
+        assert_eq!(
+            mentions(s, &["sov", "sov core"]),
+            vec!["Sov", "sov", "sov", "Sov Core"]
+        );
+    }
+
+    #[test]
+    fn mentions_skip_urls_and_tags() {
+        let s = concat!(
+            "https://example.com/sov <https://sov.org> [text](https://x.y/sov) ",
+            "[sov](sov.md) #sov #project/sov [[sov]] sov\n"
+        );
+        assert_eq!(mentions(s, &["sov"]), vec!["sov"]);
+        assert_eq!(
+            SovNote::parse_mentions(s, &["sov".to_string()])[0].0,
+            s.len() - 4
+        );
+    }
+
+    #[test]
+    fn mentions_skip_frontmatter_and_code() {
+        let s = "---\ntitle: sov\n---\n`sov` <!-- sov -->\n```\nsov\n```\nsov";
+        assert_eq!(mentions(s, &["sov"]), vec!["sov"]);
+    }
+
+    #[test]
+    fn enclosing_block_lines() {
+        let s = concat!(
+            "---\ntitle: a\n---\n",
+            "text\n",
+            "````\ncode\n```\n````\n",
+            "<!-- a\nb -->\n",
+            "~~~\n"
+        );
+        let text = Rope::from_str(s);
+        let lines: Vec<usize> = (0..12)
+            .map(|line| SovNote::enclosing_block_line(&text, line))
+            .collect();
+        assert_eq!(lines, vec![0, 0, 0, 3, 4, 4, 4, 4, 8, 8, 10, 10]);
+    }
+
+    #[test]
+    fn markdown_links() {
+        let s = "see [Notes](sub/My%20Note.md#Head%20One) and [b](n.md#^abc)";
+        let links = SovNote::parse_links(s).unwrap();
+        assert_eq!(links.len(), 2);
+        let link = &links[0];
+        assert_eq!(link.kind, LinkKind::Markdown);
+        assert_eq!(link.value, "My Note");
+        assert_eq!(link.alias.as_deref(), Some("Notes"));
+        assert_eq!(link.header.as_deref(), Some("Head One"));
+        assert_eq!(link.path.as_deref(), Some("sub/My Note.md"));
+        assert_eq!((link.start, link.end), (4, 39));
+        assert_eq!(links[1].value, "n");
+        assert_eq!(
+            (links[1].header.as_deref(), links[1].block.as_deref()),
+            (None, Some("abc"))
+        );
+
+        let links = SovNote::parse_links("[a](<a b.md>)").unwrap();
+        assert_eq!(links[0].path.as_deref(), Some("a b.md"));
+    }
+
+    fn link_offsets(s: &str) -> Vec<(String, usize, usize)> {
+        SovNote::parse_links(s)
+            .unwrap()
+            .into_iter()
+            .map(|l| (l.value, l.start, l.end))
+            .collect()
+    }
+
+    #[test]
+    fn links_in_inline_code_are_skipped() {
+        let s = "`[[a]]` [[b]] ``x [[c]]`` [[d]]";
+        assert_eq!(
+            link_offsets(s),
+            vec![("b".into(), 8, 12), ("d".into(), 26, 30)]
+        );
+    }
+
+    #[test]
+    fn links_in_code_blocks_are_skipped() {
+        let s = "```\n[[a]]\n```\n[[b]]\n~~~md\n[[c]]\n~~~\n[[d]]";
+        assert_eq!(
+            link_offsets(s),
+            vec![("b".into(), 14, 18), ("d".into(), 36, 40)]
+        );
+    }
+
+    #[test]
+    fn links_in_html_comments_are_skipped() {
+        let s = "<!-- [[a]] --> [[b]]\n<!--\n[[c]]\n-->\n[[d]] <!-- [[e]] -->";
+        assert_eq!(
+            link_offsets(s),
+            vec![("b".into(), 15, 19), ("d".into(), 36, 40)]
+        );
+        let s = "x <!-- [[a]] --> [[b]]";
+        assert_eq!(link_offsets(s), vec![("b".into(), 17, 21)]);
+    }
+
+    #[test]
+    fn link_offsets_are_chars_after_skipped_ranges() {
+        let s = "é ü `ç` [[ñ]] <!-- ö --> [[b]]";
+        assert_eq!(
+            link_offsets(s),
+            vec![("ñ".into(), 8, 12), ("b".into(), 25, 29)]
+        );
+        let s = "日本 `[[a]]`\n```\n[[b]]\n```\n[[語]]";
+        assert_eq!(link_offsets(s), vec![("語".into(), 25, 29)]);
+    }
+
+    #[test]
+    fn markdown_links_to_other_files_are_skipped() {
+        let s = "[u](https://x.y/n.md) [m](mailto:a@b.md) [i](img.png) [n](\nn.md)";
+        assert!(SovNote::parse_links(s).unwrap().is_empty());
     }
 }
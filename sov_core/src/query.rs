@@ -0,0 +1,494 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::error::{Result, SovError};
+
+/// A boolean expression over notes, e.g.
+/// `tag:project AND prop.status=open AND NOT links-to:Roadmap`.
+///
+/// Terms:
+/// - `tag:<tag>`: notes tagged with `<tag>` or one of its nested tags
+/// - `prop.<key>`: notes having the frontmatter property `<key>`
+/// - `prop.<key><op><value>`: with `<op>` one of `=`, `!=`, `<`, `<=`, `>`, `>=`
+/// - `links-to:<note>`: notes linking to `<note>`, resolved like a link (e.g.
+///   by alias or path-qualified name)
+/// - `path:<dir>`: notes in `<dir>` or its subdirs, relative to the notes dir,
+///   or the note at this path
+/// - `text:<text>`: full-text search, see [`crate::Sov::search_text`]
+///
+/// Terms are combined with `AND`, `OR`, `NOT` and parentheses, adjacent terms
+/// are implicitly combined with `AND`. Values containing spaces can be quoted.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+    Not(Box<Query>),
+    Tag(String),
+    Property {
+        key: String,
+        /// `None` only checks that the property exists
+        cmp: Option<(CmpOp, String)>,
+    },
+    LinksTo(String),
+    Path(String),
+    Text(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QuerySort {
+    #[default]
+    Path,
+    Name,
+    /// Most recently modified first
+    Modified,
+}
+
+/// A [`Query`] compiled to an SQL condition on the `note` table (aliased as
+/// `n`)
+#[derive(Debug)]
+pub struct QuerySql {
+    pub cond: String,
+    pub params: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Term(String),
+}
+
+impl Query {
+    pub fn parse(s: &str) -> Result<Query> {
+        let tokens = Self::tokenize(s)?;
+        let mut tokens = tokens.into_iter().peekable();
+        let query = Self::parse_or(&mut tokens)?;
+        if let Some(token) = tokens.next() {
+            return Err(SovError::InvalidQuery(format!(
+                "unexpected token: {:?}",
+                token
+            )));
+        }
+        Ok(query)
+    }
+
+    fn tokenize(s: &str) -> Result<Vec<Token>> {
+        let mut tokens = Vec::new();
+        let mut chars = s.chars().peekable();
+        while let Some(&c) = chars.peek() {
+            match c {
+                c if c.is_whitespace() => {
+                    chars.next();
+                }
+                '(' => {
+                    chars.next();
+                    tokens.push(Token::LParen);
+                }
+                ')' => {
+                    chars.next();
+                    tokens.push(Token::RParen);
+                }
+                _ => {
+                    let mut word = String::new();
+                    let mut quoted = false;
+                    while let Some(&c) = chars.peek() {
+                        match c {
+                            '"' => {
+                                chars.next();
+                                quoted = true;
+                                loop {
+                                    match chars.next() {
+                                        Some('"') => break,
+                                        Some(c) => word.push(c),
+                                        None => {
+                                            return Err(SovError::InvalidQuery(
+                                                "unterminated quote".into(),
+                                            ))
+                                        }
+                                    }
+                                }
+                            }
+                            c if c.is_whitespace() || c == '(' || c == ')' => break,
+                            c => {
+                                chars.next();
+                                word.push(c);
+                            }
+                        }
+                    }
+                    let token = match word.as_str() {
+                        _ if quoted => Token::Term(word),
+                        "AND" | "and" => Token::And,
+                        "OR" | "or" => Token::Or,
+                        "NOT" | "not" => Token::Not,
+                        _ => Token::Term(word),
+                    };
+                    tokens.push(token);
+                }
+            }
+        }
+        Ok(tokens)
+    }
+
+    fn parse_or<I>(tokens: &mut std::iter::Peekable<I>) -> Result<Query>
+    where
+        I: Iterator<Item = Token>,
+    {
+        let mut query = Self::parse_and(tokens)?;
+        while tokens.next_if_eq(&Token::Or).is_some() {
+            let rhs = Self::parse_and(tokens)?;
+            query = Query::Or(Box::new(query), Box::new(rhs));
+        }
+        Ok(query)
+    }
+
+    fn parse_and<I>(tokens: &mut std::iter::Peekable<I>) -> Result<Query>
+    where
+        I: Iterator<Item = Token>,
+    {
+        let mut query = Self::parse_not(tokens)?;
+        loop {
+            match tokens.peek() {
+                Some(Token::And) => {
+                    tokens.next();
+                }
+                // Implicit AND
+                Some(Token::Not | Token::LParen | Token::Term(_)) => (),
+                _ => break,
+            }
+            let rhs = Self::parse_not(tokens)?;
+            query = Query::And(Box::new(query), Box::new(rhs));
+        }
+        Ok(query)
+    }
+
+    fn parse_not<I>(tokens: &mut std::iter::Peekable<I>) -> Result<Query>
+    where
+        I: Iterator<Item = Token>,
+    {
+        match tokens.next() {
+            Some(Token::Not) => Ok(Query::Not(Box::new(Self::parse_not(tokens)?))),
+            Some(Token::LParen) => {
+                let query = Self::parse_or(tokens)?;
+                match tokens.next() {
+                    Some(Token::RParen) => Ok(query),
+                    _ => Err(SovError::InvalidQuery("missing `)`".into())),
+                }
+            }
+            Some(Token::Term(term)) => Self::parse_term(&term),
+            Some(token) => Err(SovError::InvalidQuery(format!(
+                "unexpected token: {:?}",
+                token
+            ))),
+            None => Err(SovError::InvalidQuery("unexpected end of query".into())),
+        }
+    }
+
+    fn parse_term(term: &str) -> Result<Query> {
+        if let Some(prop) = term.strip_prefix("prop.") {
+            let Some(op_start) = prop.find(['=', '!', '<', '>']) else {
+                return Ok(Query::Property {
+                    key: prop.to_string(),
+                    cmp: None,
+                });
+            };
+            let (key, rest) = prop.split_at(op_start);
+            let (op, value) = [
+                ("!=", CmpOp::Ne),
+                ("<=", CmpOp::Le),
+                (">=", CmpOp::Ge),
+                ("=", CmpOp::Eq),
+                ("<", CmpOp::Lt),
+                (">", CmpOp::Gt),
+            ]
+            .into_iter()
+            .find_map(|(s, op)| Some((op, rest.strip_prefix(s)?)))
+            .ok_or(SovError::InvalidQuery(term.to_string()))?;
+            return Ok(Query::Property {
+                key: key.to_string(),
+                cmp: Some((op, value.to_string())),
+            });
+        }
+
+        let (field, value) = term
+            .split_once(':')
+            .ok_or(SovError::InvalidQuery(term.to_string()))?;
+        let value = value.to_string();
+        match field {
+            "tag" => Ok(Query::Tag(value.trim_start_matches('#').to_string())),
+            "links-to" => Ok(Query::LinksTo(value)),
+            "path" => Ok(Query::Path(value)),
+            "text" => Ok(Query::Text(value)),
+            _ => Err(SovError::InvalidQuery(format!("unknown field: {}", field))),
+        }
+    }
+
+    /// Compiles the query, `notes_dir` is used to resolve `path:` terms and
+    /// `resolve` to resolve the note of `links-to:` terms into its id
+    pub fn to_sql(
+        &self,
+        notes_dir: &Path,
+        resolve: &dyn Fn(&str) -> Result<u64>,
+    ) -> Result<QuerySql> {
+        let mut params = Vec::new();
+        let cond = self.compile(notes_dir, resolve, &mut params)?;
+        Ok(QuerySql { cond, params })
+    }
+
+    fn compile(
+        &self,
+        notes_dir: &Path,
+        resolve: &dyn Fn(&str) -> Result<u64>,
+        params: &mut Vec<String>,
+    ) -> Result<String> {
+        let cond = match self {
+            Query::And(lhs, rhs) => format!(
+                "({} AND {})",
+                lhs.compile(notes_dir, resolve, params)?,
+                rhs.compile(notes_dir, resolve, params)?
+            ),
+            Query::Or(lhs, rhs) => format!(
+                "({} OR {})",
+                lhs.compile(notes_dir, resolve, params)?,
+                rhs.compile(notes_dir, resolve, params)?
+            ),
+            Query::Not(query) => format!("(NOT {})", query.compile(notes_dir, resolve, params)?),
+            Query::Tag(tag) => {
+                params.push(tag.clone());
+                "n.note_id IN (
+                    SELECT tn.note_id FROM tag_note tn JOIN tag t USING(tag_id)
+                    WHERE t.name = ?1 OR substr(t.name, 1, length(?1) + 1) = ?1 || '/'
+                )"
+                .replace("?1", &format!("?{}", params.len()))
+            }
+            Query::Property { key, cmp } => {
+                params.push(key.clone());
+                let key_param = params.len();
+                let Some((op, value)) = cmp else {
+                    return Ok(format!(
+                        "n.note_id IN (SELECT note_id FROM property WHERE key = ?{})",
+                        key_param
+                    ));
+                };
+                params.push(value.clone());
+                let value_param = params.len();
+                // Numbers are compared as numbers, everything else as text
+                // (which works for `YYYY-MM-DD` dates)
+                format!(
+                    "n.note_id IN (
+                        SELECT note_id FROM property WHERE key = ?{k} AND (
+                            (kind = 'number' AND CAST(value AS REAL) {op} CAST(?{v} AS REAL))
+                            OR (kind != 'number' AND value {op} ?{v})
+                        )
+                    )",
+                    k = key_param,
+                    v = value_param,
+                    op = op.as_sql()
+                )
+            }
+            Query::LinksTo(note) => {
+                // Resolved like links, e.g. by alias or path-qualified name
+                params.push(resolve(note)?.to_string());
+                format!(
+                    "n.note_id IN (SELECT l.src_note FROM link l WHERE l.target_note = ?{})",
+                    params.len()
+                )
+            }
+            Query::Path(dir) => {
+                // Whole path components only, `path:proj` must not match
+                // `projects/`
+                let path = notes_dir.join(dir.trim_end_matches('/'));
+                let path = path.to_string_lossy().to_string();
+                params.push(format!("{}/", path));
+                params.push(path);
+                format!(
+                    "(n.path = ?{1} OR substr(n.path, 1, length(?{0})) = ?{0})",
+                    params.len() - 1,
+                    params.len()
+                )
+            }
+            Query::Text(text) => {
+                // Searched as an FTS5 phrase
+                params.push(fts_phrase(text));
+                format!(
+                    "n.note_id IN (SELECT note_id FROM note_fts WHERE note_fts MATCH ?{})",
+                    params.len()
+                )
+            }
+        };
+        Ok(cond)
+    }
+}
+
+impl CmpOp {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            CmpOp::Eq => "=",
+            CmpOp::Ne => "!=",
+            CmpOp::Lt => "<",
+            CmpOp::Le => "<=",
+            CmpOp::Gt => ">",
+            CmpOp::Ge => ">=",
+        }
+    }
+}
+
+impl QuerySort {
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            QuerySort::Path => "n.path",
+            QuerySort::Name => "n.filename",
+            QuerySort::Modified => "n.mtime DESC",
+        }
+    }
+}
+
+/// Quotes `s` as an FTS5 string, its words are searched as a phrase
+pub(crate) fn fts_phrase(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "\"\""))
+}
+
+/// Converts free text into an FTS5 query matching notes containing all of its
+/// words, a trailing `*` searches a prefix (e.g. `proj*`)
+pub(crate) fn fts_terms(s: &str) -> String {
+    s.split_whitespace()
+        .map(|word| match word.strip_suffix('*') {
+            Some(prefix) if !prefix.is_empty() => format!("{}*", fts_phrase(prefix)),
+            _ => fts_phrase(word),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl FromStr for QuerySort {
+    type Err = SovError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "path" => Ok(QuerySort::Path),
+            "name" => Ok(QuerySort::Name),
+            "modified" => Ok(QuerySort::Modified),
+            _ => Err(SovError::InvalidQuery(format!(
+                "invalid sort: {}, expected path, name or modified",
+                s
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(t: &str) -> Query {
+        Query::Tag(t.to_string())
+    }
+
+    fn and(a: Query, b: Query) -> Query {
+        Query::And(Box::new(a), Box::new(b))
+    }
+
+    fn or(a: Query, b: Query) -> Query {
+        Query::Or(Box::new(a), Box::new(b))
+    }
+
+    fn not(a: Query) -> Query {
+        Query::Not(Box::new(a))
+    }
+
+    #[test]
+    fn quoted_values() {
+        assert_eq!(
+            Query::parse(r#"links-to:"My Note" path:"a b/""#).unwrap(),
+            and(Query::LinksTo("My Note".into()), Query::Path("a b/".into()))
+        );
+        assert_eq!(
+            Query::parse(r#"prop.title="a OR b""#).unwrap(),
+            Query::Property {
+                key: "title".into(),
+                cmp: Some((CmpOp::Eq, "a OR b".into())),
+            }
+        );
+        assert!(Query::parse(r#"tag:"open"#).is_err());
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and_tighter_than_or() {
+        assert_eq!(
+            Query::parse("NOT tag:a OR tag:b tag:c").unwrap(),
+            or(not(tag("a")), and(tag("b"), tag("c")))
+        );
+        assert_eq!(
+            Query::parse("tag:a AND NOT (tag:b OR tag:c)").unwrap(),
+            and(tag("a"), not(or(tag("b"), tag("c"))))
+        );
+        assert_eq!(Query::parse("NOT NOT tag:a").unwrap(), not(not(tag("a"))));
+    }
+
+    #[test]
+    fn invalid_queries() {
+        for query in [
+            "(tag:a",
+            "tag:a)",
+            "tag:a AND",
+            "OR tag:a",
+            "nofield",
+            "x:y",
+        ] {
+            assert!(Query::parse(query).is_err(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn property_operators() {
+        let cmp = |s: &str| match Query::parse(s).unwrap() {
+            Query::Property { cmp, .. } => cmp,
+            q => panic!("{:?}", q),
+        };
+        assert_eq!(cmp("prop.n>=2"), Some((CmpOp::Ge, "2".into())));
+        assert_eq!(cmp("prop.n!=2"), Some((CmpOp::Ne, "2".into())));
+        assert_eq!(cmp("prop.n"), None);
+    }
+
+    #[test]
+    fn path_matches_whole_components() {
+        let sql = Query::Path("proj/".into())
+            .to_sql(Path::new("/notes"), &|_| Ok(0))
+            .unwrap();
+        assert_eq!(sql.params, vec!["/notes/proj/", "/notes/proj"]);
+    }
+
+    #[test]
+    fn links_to_is_resolved() {
+        let resolve = |name: &str| match name {
+            "Note" => Ok(42),
+            _ => Err(SovError::NoteNotFound(name.to_string())),
+        };
+        let sql = Query::parse("links-to:Note")
+            .unwrap()
+            .to_sql(Path::new("/notes"), &resolve)
+            .unwrap();
+        assert_eq!(sql.params, vec!["42"]);
+        let query = Query::parse("links-to:Missing").unwrap();
+        assert!(query.to_sql(Path::new("/notes"), &resolve).is_err());
+    }
+
+    #[test]
+    fn fts_terms_are_quoted() {
+        assert_eq!(fts_terms("v1.2 foo-bar"), r#""v1.2" "foo-bar""#);
+        assert_eq!(fts_terms(r#"say "hi" proj*"#), r#""say" """hi""" "proj"*"#);
+        assert_eq!(fts_terms("  "), "");
+    }
+}
@@ -0,0 +1,319 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+
+use crate::config::LinkMatching;
+use crate::note::{LinkKind, SovNote};
+
+/// Resolves note names and links to notes.
+///
+/// A name is either a filename (e.g. `index`), which may match several notes
+/// in different directories, or a path-qualified name relative to the notes
+/// dir (e.g. `projects/sov/index`), which also matches as a path suffix (e.g.
+/// `sov/index`) unless a note has this exact path. A leading `/` anchors the
+/// name to the notes dir (e.g. `/sov/index` never matches
+/// `projects/sov/index`). Filenames are looked up first, then the note
+/// aliases.
+///
+/// Names are matched according to a [`LinkMatching`], e.g. case-insensitively.
+pub struct Resolver {
+    notes_dir: PathBuf,
+    matching: LinkMatching,
+    /// `(note_id, path)` of all notes
+    notes: Vec<(u64, PathBuf)>,
+    /// By match key, see [`LinkMatching::key`]
+    by_filename: HashMap<String, Vec<usize>>,
+    /// By match key, see [`LinkMatching::key`]
+    by_alias: HashMap<String, Vec<usize>>,
+    by_path: HashMap<PathBuf, usize>,
+}
+
+#[derive(Debug)]
+pub enum Target {
+    Missing,
+    Note(u64, PathBuf),
+    /// Several notes match, sorted by path
+    Ambiguous(Vec<PathBuf>),
+}
+
+impl Resolver {
+    /// `aliases` are `(alias, note_id)` pairs
+    pub fn new(
+        notes_dir: &Path,
+        matching: LinkMatching,
+        notes: Vec<(u64, PathBuf)>,
+        aliases: Vec<(String, u64)>,
+    ) -> Self {
+        let mut by_filename: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut by_id = HashMap::new();
+        let mut by_path = HashMap::new();
+        for (i, (note_id, path)) in notes.iter().enumerate() {
+            if let Some(filename) = SovNote::filename(path) {
+                by_filename
+                    .entry(matching.key(&filename))
+                    .or_default()
+                    .push(i);
+            }
+            by_id.insert(*note_id, i);
+            by_path.insert(path.clone(), i);
+        }
+        let mut by_alias: HashMap<String, Vec<usize>> = HashMap::new();
+        for (alias, note_id) in aliases {
+            if let Some(&i) = by_id.get(&note_id) {
+                by_alias.entry(matching.key(&alias)).or_default().push(i);
+            }
+        }
+        Self {
+            notes_dir: notes_dir.to_path_buf(),
+            matching,
+            notes,
+            by_filename,
+            by_alias,
+            by_path,
+        }
+    }
+
+    /// Resolves a note name, see [`Resolver`], or the absolute path of a note
+    pub fn resolve(&self, name: &str) -> Target {
+        let path = Path::new(name);
+        if path.is_absolute() && path.starts_with(&self.notes_dir) {
+            // Filenames may contain dots, e.g. `Meeting v1.2.md`
+            let path = match path.extension() {
+                Some(ext) if ext == "md" => path.to_path_buf(),
+                _ => PathBuf::from(format!("{}.md", name)),
+            };
+            return self.target(self.by_path.get(&path).copied().into_iter());
+        }
+        let name = name.strip_suffix(".md").unwrap_or(name);
+        // e.g. `/a/index` only matches `a/index.md` in the notes dir
+        let (anchored, name) = match name.strip_prefix('/') {
+            Some(name) => (true, name),
+            None => (false, name),
+        };
+
+        let name = self.matching.key(name);
+        let (dir, filename) = match name.rsplit_once('/') {
+            Some((dir, filename)) => (Some(dir), filename),
+            None => (None, name.as_str()),
+        };
+        let candidates = match (self.by_filename.get(filename), dir, anchored) {
+            (Some(candidates), _, _) => candidates,
+            // Aliases are not path-qualified
+            (None, None, false) => match self.by_alias.get(filename) {
+                Some(candidates) => candidates,
+                None => return Target::Missing,
+            },
+            _ => return Target::Missing,
+        };
+        if dir.is_none() && !anchored {
+            return self.target(candidates.iter().copied());
+        }
+
+        // Matches of the full path relative to the notes dir, and of a path
+        // suffix
+        let dir = dir.unwrap_or_default();
+        let mut full = Vec::new();
+        let mut suffix = Vec::new();
+        for &i in candidates {
+            let note_dir = self.notes[i].1.parent().unwrap_or(Path::new(""));
+            let note_dir = note_dir.strip_prefix(&self.notes_dir).unwrap_or(note_dir);
+            let note_dir = self.matching.key(&note_dir.to_string_lossy());
+            if Path::new(&note_dir) == Path::new(dir) {
+                full.push(i);
+            } else if !anchored && Path::new(&note_dir).ends_with(dir) {
+                suffix.push(i);
+            }
+        }
+        // A full path wins, e.g. `a/index` over `x/a/index`
+        match full.is_empty() {
+            true => self.target(suffix.into_iter()),
+            false => self.target(full.into_iter()),
+        }
+    }
+
+    /// The key under which a link value is matched with the filenames and
+    /// aliases, e.g. `index` for `projects/sov/index`
+    pub fn link_key(&self, value: &str) -> String {
+        let value = value.strip_suffix(".md").unwrap_or(value);
+        self.matching
+            .key(value.rsplit('/').next().unwrap_or_default())
+    }
+
+    /// The keys of the filenames and aliases that match other notes than in
+    /// `old`, the links matching them may resolve differently
+    pub fn changed_keys(&self, old: &Resolver) -> HashSet<String> {
+        let note_ids = |r: &Resolver, key: &str| {
+            let ids = |by_key: &HashMap<String, Vec<usize>>| {
+                let ids = by_key.get(key).into_iter().flatten();
+                ids.map(|&i| r.notes[i].0).collect::<BTreeSet<u64>>()
+            };
+            (ids(&r.by_filename), ids(&r.by_alias))
+        };
+        let keys: HashSet<&String> = self
+            .by_filename
+            .keys()
+            .chain(self.by_alias.keys())
+            .chain(old.by_filename.keys())
+            .chain(old.by_alias.keys())
+            .collect();
+        keys.into_iter()
+            .filter(|key| note_ids(self, key) != note_ids(old, key))
+            .cloned()
+            .collect()
+    }
+
+    /// The name qualified by its path relative to the notes dir of the note at
+    /// `path`, e.g. `projects/sov/index`, if it resolves to this note alone
+    pub fn qualified_name(&self, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(&self.notes_dir).ok()?;
+        let name = relative.with_extension("").to_string_lossy().to_string();
+        match self.resolve(&name) {
+            Target::Note(_, target) if target == path => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Resolves a link of the note at `src_path`, markdown links are resolved
+    /// by their path relative to the note
+    pub fn resolve_link(
+        &self,
+        src_path: &Path,
+        kind: LinkKind,
+        value: &str,
+        path: Option<&str>,
+    ) -> Target {
+        match (kind, path) {
+            (LinkKind::ImageEmbed, _) => Target::Missing,
+            (LinkKind::Markdown, Some(path)) => {
+                let dir = src_path.parent().unwrap_or(Path::new(""));
+                let path = Self::normalize(&dir.join(path));
+                self.target(self.by_path.get(&path).copied().into_iter())
+            }
+            _ => self.resolve(value),
+        }
+    }
+
+    fn target(&self, candidates: impl Iterator<Item = usize>) -> Target {
+        let mut candidates: Vec<_> = candidates.map(|i| &self.notes[i]).collect();
+        match candidates.len() {
+            0 => Target::Missing,
+            1 => Target::Note(candidates[0].0, candidates[0].1.clone()),
+            _ => {
+                candidates.sort_by(|a, b| a.1.cmp(&b.1));
+                Target::Ambiguous(candidates.into_iter().map(|(_, p)| p.clone()).collect())
+            }
+        }
+    }
+
+    /// Resolves the `.` and `..` components of a path without touching the
+    /// filesystem
+    pub(crate) fn normalize(path: &Path) -> PathBuf {
+        let mut normalized = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::CurDir => (),
+                Component::ParentDir => {
+                    normalized.pop();
+                }
+                c => normalized.push(c),
+            }
+        }
+        normalized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolver(paths: &[&str]) -> Resolver {
+        let notes = paths
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (i as u64, PathBuf::from(p)))
+            .collect();
+        Resolver::new(Path::new("/v"), LinkMatching::default(), notes, Vec::new())
+    }
+
+    fn resolved_id(target: Target) -> Option<u64> {
+        match target {
+            Target::Note(note_id, _) => Some(note_id),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn dotted_filenames() {
+        let r = resolver(&["/v/Meeting v1.2.md", "/v/Meeting v1.md"]);
+        for name in [
+            "/v/Meeting v1.2.md",
+            "/v/Meeting v1.2",
+            "Meeting v1.2",
+            "Meeting v1.2.md",
+        ] {
+            assert_eq!(resolved_id(r.resolve(name)), Some(0), "{}", name);
+        }
+        assert_eq!(resolved_id(r.resolve("/v/Meeting v1")), Some(1));
+    }
+
+    #[test]
+    fn path_qualified_names() {
+        let r = resolver(&["/v/a/index.md", "/v/b/c/index.md"]);
+        assert!(matches!(r.resolve("index"), Target::Ambiguous(p) if p.len() == 2));
+        assert_eq!(resolved_id(r.resolve("a/index")), Some(0));
+        assert_eq!(resolved_id(r.resolve("c/index")), Some(1));
+        assert_eq!(resolved_id(r.resolve("b/c/index")), Some(1));
+        assert!(matches!(r.resolve("b/index"), Target::Missing));
+    }
+
+    #[test]
+    fn full_paths_win_over_suffixes() {
+        let r = resolver(&["/v/a/index.md", "/v/x/a/index.md"]);
+        assert_eq!(resolved_id(r.resolve("a/index")), Some(0));
+        assert_eq!(resolved_id(r.resolve("/a/index")), Some(0));
+        assert_eq!(resolved_id(r.resolve("x/a/index")), Some(1));
+        assert_eq!(resolved_id(r.resolve("/x/a/index")), Some(1));
+        assert!(matches!(r.resolve("/index"), Target::Missing));
+
+        let r = resolver(&["/v/x/a/index.md", "/v/y/a/index.md"]);
+        assert!(matches!(r.resolve("a/index"), Target::Ambiguous(p) if p.len() == 2));
+        assert!(matches!(r.resolve("/a/index"), Target::Missing));
+    }
+
+    #[test]
+    fn changed_keys() {
+        let old = resolver(&["/v/a.md", "/v/b.md", "/v/c.md"]);
+        // `b` is removed, `c` is indexed again under a new id, `d` is added
+        let notes = vec![
+            (0, PathBuf::from("/v/a.md")),
+            (3, PathBuf::from("/v/c.md")),
+            (4, PathBuf::from("/v/d.md")),
+        ];
+        let aliases = vec![("Alias".to_string(), 0)];
+        let new = Resolver::new(Path::new("/v"), LinkMatching::default(), notes, aliases);
+        let mut keys: Vec<String> = new.changed_keys(&old).into_iter().collect();
+        keys.sort();
+        assert_eq!(keys, vec!["Alias", "b", "c", "d"]);
+        assert!(keys.contains(&new.link_key("x/Alias.md")));
+    }
+
+    #[test]
+    fn qualified_names_resolve_to_their_note() {
+        let r = resolver(&["/v/a/index.md", "/v/x/a/index.md"]);
+        let name = r.qualified_name(Path::new("/v/a/index.md"));
+        assert_eq!(name.as_deref(), Some("a/index"));
+        let name = r.qualified_name(Path::new("/v/x/a/index.md"));
+        assert_eq!(name.as_deref(), Some("x/a/index"));
+
+        // Only told apart by their case
+        let notes = vec![
+            (0, PathBuf::from("/v/A/n.md")),
+            (1, PathBuf::from("/v/a/n.md")),
+        ];
+        let matching = LinkMatching {
+            case_insensitive: true,
+            unicode_normalization: false,
+        };
+        let r = Resolver::new(Path::new("/v"), matching, notes, Vec::new());
+        assert_eq!(r.qualified_name(Path::new("/v/a/n.md")), None);
+    }
+}
@@ -0,0 +1,442 @@
+use std::collections::{BTreeMap, HashSet};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use chrono::{DateTime, Local, NaiveDate};
+use ropey::Rope;
+use serde::Serialize;
+
+use crate::error::{Result, SovError};
+use crate::note::SovNote;
+
+/// A tag with the notes using it
+#[derive(Debug, Clone, Serialize)]
+pub struct TagUsage {
+    pub tag: String,
+    /// Number of notes using the tag
+    pub count: usize,
+    /// Modification date of the least recently modified note using the tag
+    pub oldest_modified: NaiveDate,
+    /// Modification date of the most recently modified note using the tag
+    pub newest_modified: NaiveDate,
+    pub notes: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TagSort {
+    #[default]
+    Name,
+    /// Most used first
+    Count,
+    /// Tags of the most recently modified notes first
+    Modified,
+}
+
+impl TagUsage {
+    pub fn new(tag: String) -> Self {
+        Self {
+            tag,
+            count: 0,
+            oldest_modified: NaiveDate::MAX,
+            newest_modified: NaiveDate::MIN,
+            notes: Vec::new(),
+        }
+    }
+
+    /// Adds a note using the tag, `mtime` is in nanoseconds since the epoch
+    pub fn add_note(&mut self, path: PathBuf, mtime: i64) {
+        let date = DateTime::from_timestamp_nanos(mtime)
+            .with_timezone(&Local)
+            .date_naive();
+        self.oldest_modified = self.oldest_modified.min(date);
+        self.newest_modified = self.newest_modified.max(date);
+        self.count += 1;
+        self.notes.push(path);
+    }
+
+    pub fn sort(tags: &mut [TagUsage], sort: TagSort) {
+        match sort {
+            TagSort::Name => tags.sort_by(|a, b| a.tag.cmp(&b.tag)),
+            TagSort::Count => {
+                tags.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.tag.cmp(&b.tag)))
+            }
+            TagSort::Modified => tags.sort_by(|a, b| {
+                b.newest_modified
+                    .cmp(&a.newest_modified)
+                    .then_with(|| a.tag.cmp(&b.tag))
+            }),
+        }
+    }
+}
+
+impl FromStr for TagSort {
+    type Err = SovError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "name" => Ok(TagSort::Name),
+            "count" => Ok(TagSort::Count),
+            "modified" => Ok(TagSort::Modified),
+            _ => Err(SovError::InvalidTagSort(s.to_string())),
+        }
+    }
+}
+
+/// A node of the nested tags hierarchy, e.g. `lsp` in `project/sov/lsp`
+#[derive(Debug, Serialize)]
+pub struct TagNode {
+    /// Last segment of the tag, e.g. `lsp`
+    pub name: String,
+    /// Full tag, e.g. `project/sov/lsp`
+    pub tag: String,
+    /// Number of notes tagged with this tag or one of its descendants
+    pub count: usize,
+    pub children: Vec<TagNode>,
+}
+
+impl TagNode {
+    pub const SEPARATOR: char = '/';
+
+    /// Builds the tags hierarchy from `(tag, note_id)` pairs
+    pub fn build_tree(tag_notes: &[(String, u64)]) -> Vec<TagNode> {
+        // Notes of every tag and of every parent tag
+        let mut notes: BTreeMap<&str, HashSet<u64>> = BTreeMap::new();
+        for (tag, note_id) in tag_notes {
+            for (i, c) in tag.char_indices() {
+                if c == Self::SEPARATOR {
+                    notes.entry(&tag[..i]).or_default().insert(*note_id);
+                }
+            }
+            notes.entry(tag).or_default().insert(*note_id);
+        }
+        Self::build_children("", &notes)
+    }
+
+    fn build_children(parent: &str, notes: &BTreeMap<&str, HashSet<u64>>) -> Vec<TagNode> {
+        notes
+            .iter()
+            .filter(|(tag, _)| Self::parent(tag) == parent)
+            .map(|(tag, note_ids)| TagNode {
+                name: Self::name(tag).to_string(),
+                tag: tag.to_string(),
+                count: note_ids.len(),
+                children: Self::build_children(tag, notes),
+            })
+            .collect()
+    }
+
+    /// Finds the node of `tag` in the hierarchy
+    pub fn find<'a>(nodes: &'a [TagNode], tag: &str) -> Option<&'a TagNode> {
+        let (first, rest) = match tag.split_once(Self::SEPARATOR) {
+            Some((first, rest)) => (first, Some(rest)),
+            None => (tag, None),
+        };
+        let node = nodes.iter().find(|n| n.name == first)?;
+        match rest {
+            Some(rest) => Self::find(&node.children, rest),
+            None => Some(node),
+        }
+    }
+
+    /// The parent of a tag, e.g. `project/sov` for `project/sov/lsp`, empty for
+    /// top-level tags
+    pub fn parent(tag: &str) -> &str {
+        tag.rsplit_once(Self::SEPARATOR)
+            .map(|(parent, _)| parent)
+            .unwrap_or_default()
+    }
+
+    /// The last segment of a tag, e.g. `lsp` for `project/sov/lsp`
+    pub fn name(tag: &str) -> &str {
+        tag.rsplit_once(Self::SEPARATOR)
+            .map(|(_, name)| name)
+            .unwrap_or(tag)
+    }
+}
+
+/// Rewrites the frontmatter tags of a note as a block list of unique tags
+/// without `#`, e.g. `tags: project, #work` becomes:
+///
+/// ```yaml
+/// tags:
+///   - project
+///   - work
+/// ```
+pub fn normalize_frontmatter_tags(content: &str) -> String {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let Some(yaml_end) = frontmatter_end(content) else {
+        return content.to_string();
+    };
+    let Some(tags_line) = (1..yaml_end).find(|&i| lines[i].starts_with("tags:")) else {
+        return content.to_string();
+    };
+
+    let (value, eol) = TagRename::split_eol(&lines[tags_line]["tags:".len()..]);
+    let value = value.trim();
+    let (items, items_end) =
+        if let Some(items) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+            (items.split(',').collect(), tags_line + 1)
+        } else if !value.is_empty() {
+            (value.split([' ', ',']).collect(), tags_line + 1)
+        } else {
+            let mut items = Vec::new();
+            let mut i = tags_line + 1;
+            while let Some(item) = lines[i..yaml_end]
+                .first()
+                .and_then(|l| TagRename::split_eol(l).0.trim_start().strip_prefix('-'))
+            {
+                items.push(item);
+                i += 1;
+            }
+            (items, i)
+        };
+
+    let mut tags: Vec<&str> = Vec::new();
+    for item in items {
+        let tag = TagRename::unquote(item.trim());
+        if !tag.is_empty() && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    if tags.is_empty() {
+        return content.to_string();
+    }
+    let mut normalized = lines[..tags_line].concat();
+    normalized.push_str(&format!("tags:{}", eol));
+    for tag in tags {
+        normalized.push_str(&format!("  - {}{}", tag, eol));
+    }
+    normalized.push_str(&lines[items_end..].concat());
+    normalized
+}
+
+/// The index of the line closing the frontmatter, see
+/// [`SovNote::frontmatter`]
+fn frontmatter_end(content: &str) -> Option<usize> {
+    SovNote::frontmatter_lines(content).checked_sub(1)
+}
+
+/// Renames tags along with their nested tags, e.g. renaming `project` into
+/// `work` also renames `project/sov` into `work/sov`
+pub struct TagRename<'a> {
+    pub from: &'a [String],
+    pub into: &'a str,
+}
+
+impl TagRename<'_> {
+    /// The new name of `tag`, if renamed
+    pub fn rename(&self, tag: &str) -> Option<String> {
+        self.from.iter().find_map(|from| {
+            if tag == from {
+                return Some(self.into.to_string());
+            }
+            let rest = tag.strip_prefix(from.as_str())?;
+            rest.starts_with(TagNode::SEPARATOR)
+                .then(|| format!("{}{}", self.into, rest))
+        })
+    }
+
+    /// Renames the frontmatter tags and the inline `#tags` of a note, the rest
+    /// of the note is kept as it is
+    pub fn apply(&self, content: &str) -> String {
+        let mut text = Rope::from_str(content);
+        // Edit from the end so that the offsets of the other edits stay valid
+        for (range, new_text) in self.edits(content).into_iter().rev() {
+            text.remove(range.clone());
+            text.insert(range.start, &new_text);
+        }
+        text.to_string()
+    }
+
+    /// The changes of [`TagRename::apply`], as char ranges of `content` along
+    /// with their new text, sorted by start. The frontmatter is replaced as a
+    /// whole.
+    pub fn edits(&self, content: &str) -> Vec<(Range<usize>, String)> {
+        let mut edits = Vec::new();
+        if let Some(yaml_end) = frontmatter_end(content) {
+            let header_len = content
+                .split_inclusive('\n')
+                .take(yaml_end + 1)
+                .map(str::len)
+                .sum();
+            let header = &content[..header_len];
+            let new_header = self.apply_frontmatter(header);
+            if new_header != header {
+                edits.push((0..header.chars().count(), new_header));
+            }
+        }
+        for tag in SovNote::parse_tags(content) {
+            if let Some(new_name) = self.rename(&tag.name) {
+                edits.push((tag.start + 1..tag.end + 1, new_name));
+            }
+        }
+        edits
+    }
+
+    fn apply_frontmatter(&self, content: &str) -> String {
+        let mut lines: Vec<String> = content.split_inclusive('\n').map(String::from).collect();
+        let Some(mut yaml_end) = frontmatter_end(content) else {
+            return content.to_string();
+        };
+        let Some(tags_line) = (1..yaml_end).find(|&i| lines[i].starts_with("tags:")) else {
+            return content.to_string();
+        };
+
+        let (value, eol) = Self::split_eol(&lines[tags_line]["tags:".len()..]);
+        let value = value.trim();
+        if let Some(items) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+            // tags: [a, b]
+            let mut seen = Vec::new();
+            let mut tags = Vec::new();
+            for item in items.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+                let new_item = self.rename(Self::unquote(item));
+                let tag = new_item.clone().unwrap_or(Self::unquote(item).to_string());
+                if seen.contains(&tag) {
+                    continue;
+                }
+                tags.push(new_item.unwrap_or(item.to_string()));
+                seen.push(tag);
+            }
+            lines[tags_line] = format!("tags: [{}]{}", tags.join(", "), eol);
+        } else if !value.is_empty() {
+            // tags: a b
+            let mut tags = String::new();
+            for word in value.split_inclusive([' ', ',']) {
+                let (tag, sep) = word.split_at(word.trim_end_matches([' ', ',']).len());
+                match self.rename(Self::unquote(tag)) {
+                    Some(new_name) => tags.push_str(&new_name),
+                    None => tags.push_str(tag),
+                }
+                tags.push_str(sep);
+            }
+            lines[tags_line] = format!("tags: {}{}", tags, eol);
+        } else {
+            // tags:
+            //   - a
+            //   - b
+            let mut seen = Vec::new();
+            let mut i = tags_line + 1;
+            while i < yaml_end {
+                let (line, eol) = Self::split_eol(&lines[i]);
+                let Some(item) = line.trim_start().strip_prefix('-') else {
+                    break;
+                };
+                let indent = &line[..line.len() - line.trim_start().len()];
+                let item = item.trim();
+                let new_item = self.rename(Self::unquote(item));
+                let tag = new_item.clone().unwrap_or(Self::unquote(item).to_string());
+                if seen.contains(&tag) {
+                    lines.remove(i);
+                    yaml_end -= 1;
+                    continue;
+                }
+                if let Some(new_item) = new_item {
+                    lines[i] = format!("{}- {}{}", indent, new_item, eol);
+                }
+                seen.push(tag);
+                i += 1;
+            }
+        }
+        lines.concat()
+    }
+
+    fn split_eol(line: &str) -> (&str, &str) {
+        let content = line.trim_end_matches(['\r', '\n']);
+        (content, &line[content.len()..])
+    }
+
+    fn unquote(item: &str) -> &str {
+        let item = item.trim_matches(['"', '\'']);
+        item.strip_prefix('#').unwrap_or(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rename_nested_tags() {
+        let from = vec!["project".to_string()];
+        let rename = TagRename {
+            from: &from,
+            into: "work",
+        };
+        let s = concat!(
+            "---\ntags:\n  - project/sov\n  - work/sov\n  - other\n---\n",
+            "#project text #projects #project/sov `#project`\n"
+        );
+        assert_eq!(
+            rename.apply(s),
+            concat!(
+                "---\ntags:\n  - work/sov\n  - other\n---\n",
+                "#work text #projects #work/sov `#project`\n"
+            )
+        );
+        assert_eq!(
+            rename.apply("---\ntags: project, a\n---\n"),
+            "---\ntags: work, a\n---\n"
+        );
+    }
+
+    #[test]
+    fn rename_edits() {
+        let from = vec!["old".to_string()];
+        let rename = TagRename {
+            from: &from,
+            into: "new",
+        };
+        let s = "---\ntags: old\n---\né #old #other #old/x\n";
+        assert_eq!(
+            rename.edits(s),
+            vec![
+                (0..18, "---\ntags: new\n---\n".to_string()),
+                (21..24, "new".to_string()),
+                (33..38, "new/x".to_string()),
+            ]
+        );
+        assert!(rename.edits("#other\n").is_empty());
+    }
+
+    #[test]
+    fn normalize_tags() {
+        assert_eq!(
+            normalize_frontmatter_tags("---\ntitle: x\ntags: [\"a\", '#b', a]\n---\ntext\n"),
+            "---\ntitle: x\ntags:\n  - a\n  - b\n---\ntext\n"
+        );
+        assert_eq!(
+            normalize_frontmatter_tags("---\ntags:\n  - '#a'\n  - a\n---\n"),
+            "---\ntags:\n  - a\n---\n"
+        );
+        let s = "---\ntitle: x\n---\n";
+        assert_eq!(normalize_frontmatter_tags(s), s);
+    }
+
+    #[test]
+    fn frontmatter_closed_by_dots() {
+        let from = vec!["old".to_string()];
+        let rename = TagRename {
+            from: &from,
+            into: "new",
+        };
+        let s = "---\ntags: [old, b]\n...\n#old\n";
+        assert_eq!(rename.apply(s), "---\ntags: [new, b]\n...\n#new\n");
+        assert_eq!(
+            normalize_frontmatter_tags("---\ntags: a, #b\n...\ntext\n"),
+            "---\ntags:\n  - a\n  - b\n...\ntext\n"
+        );
+    }
+
+    #[test]
+    fn leading_thematic_break_is_not_frontmatter() {
+        let from = vec!["old".to_string()];
+        let rename = TagRename {
+            from: &from,
+            into: "new",
+        };
+        let s = "---\ntags: old\n#old\n";
+        assert_eq!(rename.apply(s), "---\ntags: old\n#new\n");
+        assert_eq!(normalize_frontmatter_tags(s), s);
+    }
+}
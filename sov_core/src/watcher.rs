@@ -0,0 +1,60 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tracing::warn;
+
+use crate::error::Result;
+
+/// Watches the notes dir for changes made outside of sov (editors, sync tools,
+/// git, ...)
+pub struct SovWatcher {
+    // The watcher stops when dropped
+    _watcher: RecommendedWatcher,
+    rx: Receiver<notify::Result<Event>>,
+}
+
+impl SovWatcher {
+    /// Events received within this delay are grouped together
+    const DEBOUNCE: Duration = Duration::from_millis(200);
+
+    pub fn new(notes_dir: &Path) -> Result<Self> {
+        let (tx, rx) = channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        watcher.watch(notes_dir, RecursiveMode::Recursive)?;
+        Ok(Self {
+            _watcher: watcher,
+            rx,
+        })
+    }
+
+    /// Blocks until some paths are created, modified, removed or renamed.
+    ///
+    /// Returns `None` once the watcher has stopped.
+    pub fn next_changes(&self) -> Option<Vec<PathBuf>> {
+        let mut paths = HashSet::new();
+        let event = self.rx.recv().ok()?;
+        Self::collect_paths(event, &mut paths);
+        loop {
+            match self.rx.recv_timeout(Self::DEBOUNCE) {
+                Ok(event) => Self::collect_paths(event, &mut paths),
+                Err(RecvTimeoutError::Timeout) if paths.is_empty() => continue,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return None,
+            }
+        }
+        Some(paths.into_iter().collect())
+    }
+
+    fn collect_paths(event: notify::Result<Event>, paths: &mut HashSet<PathBuf>) {
+        match event {
+            Ok(event) => match event.kind {
+                EventKind::Access(_) => (),
+                _ => paths.extend(event.paths),
+            },
+            Err(e) => warn!("Watcher error: {}", e),
+        }
+    }
+}
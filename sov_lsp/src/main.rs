@@ -1,10 +1,15 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
 use dashmap::DashMap;
 use linkify::{LinkFinder, LinkKind};
 use ropey::Rope;
-use sov_core::note::{Link, SovNote};
+use sov_core::lint::LintLevel;
+use sov_core::note::{Block, InlineTag, Link, SovNote};
+use sov_core::tag::{TagNode, TagRename};
 use sov_core::Sov;
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::lsp_types::*;
@@ -27,7 +32,7 @@ impl LanguageServer for SovLanguageServer {
                 )),
                 completion_provider: Some(CompletionOptions {
                     resolve_provider: Some(false),
-                    trigger_characters: Some(vec!["[".into(), "#".into()]),
+                    trigger_characters: Some(vec!["[".into(), "#".into(), "/".into()]),
                     work_done_progress_options: Default::default(),
                     all_commit_characters: None,
                     completion_item: None,
@@ -35,8 +40,16 @@ impl LanguageServer for SovLanguageServer {
                 definition_provider: Some(OneOf::Left(true)),
                 references_provider: Some(OneOf::Left(true)),
                 rename_provider: Some(OneOf::Left(true)),
+                code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+                workspace_symbol_provider: Some(OneOf::Left(true)),
                 execute_command_provider: Some(ExecuteCommandOptions {
-                    commands: vec!["sov.index".into(), "sov.daily".into()],
+                    commands: vec![
+                        "sov.index".into(),
+                        "sov.daily".into(),
+                        "sov.block.insert".into(),
+                        "sov.query".into(),
+                        "sov.list.unlinked-mentions".into(),
+                    ],
                     ..Default::default()
                 }),
                 ..Default::default()
@@ -49,6 +62,8 @@ impl LanguageServer for SovLanguageServer {
         self.client
             .log_message(MessageType::ERROR, "server initialized!")
             .await;
+        self.index().await;
+        self.start_watcher();
     }
 
     async fn shutdown(&self) -> Result<()> {
@@ -115,11 +130,29 @@ impl LanguageServer for SovLanguageServer {
                 cmp1.cmp(&cmp2)
             });
             if let Some(link) = link {
+                let src_path = Self::uri_to_path(&uri).ok()?;
                 let sov = self.sov.lock().unwrap();
-                let note_path = sov.resolve_note(&link.value).ok()??;
+                let note_path = sov.resolve_link(&src_path, link).ok()??;
                 let note_uri = Self::path_to_uri(&note_path).ok()?;
-
-                let range = Range::default();
+                // The path is used as the note name as filenames may be ambiguous
+                let note = note_path.to_str()?;
+
+                let line = if let Some(header) = &link.header {
+                    let heading = sov.resolve_heading(note, header).ok()?;
+                    heading.map(|(_, h)| h.start)
+                } else if let Some(block) = &link.block {
+                    let block = sov.resolve_block(note, block).ok()?;
+                    block.map(|(_, b)| b.line)
+                } else {
+                    None
+                };
+                let range = match line {
+                    Some(line) => {
+                        let pos = Position::new(line as u32, 0);
+                        Range::new(pos, pos)
+                    }
+                    None => Range::default(),
+                };
                 Some(GotoDefinitionResponse::Scalar(Location::new(
                     note_uri, range,
                 )))
@@ -151,6 +184,10 @@ impl LanguageServer for SovLanguageServer {
         let completions = || -> Option<Vec<CompletionItem>> {
             let rope = self.document_map.get(&uri.to_string())?;
             let line = rope.get_line(position.line as usize)?;
+            let before: String = line.chars().take(position.character as usize).collect();
+            if let Some(tag) = Self::tag_under_cursor(&before) {
+                return self.tag_completions(tag, &position);
+            }
             match line.as_str()?.as_bytes()[..position.character as usize] {
                 [.., b'[', b'['] => {
                     let mut ret = Vec::new();
@@ -181,20 +218,6 @@ impl LanguageServer for SovLanguageServer {
 
                     Some(ret)
                 }
-                [.., b'#'] => {
-                    let tags = self.sov.lock().unwrap().list_tags().ok()?;
-                    let mut ret = Vec::new();
-                    for tag in tags {
-                        let tag = format!("#{}", tag);
-                        let completion = CompletionItem {
-                            label: tag,
-                            kind: Some(CompletionItemKind::CONSTANT),
-                            ..Default::default()
-                        };
-                        ret.push(completion);
-                    }
-                    Some(ret)
-                }
                 _ => None,
             }
         }();
@@ -218,14 +241,16 @@ impl LanguageServer for SovLanguageServer {
         let references = async {
             let rope = self.document_map.get(&uri.to_string())?;
             let line = rope.get_line(position.line as usize)?;
-            let filename = if let Some(link) = Self::link_under_cursor(&position, line.as_str()?) {
-                link.value
-            } else {
-                let path = Self::uri_to_path(&uri).ok()?;
-                path.file_stem()?.to_str()?.to_string()
+            let src_path = Self::uri_to_path(&uri).ok()?;
+            let sov = self.sov.lock().unwrap();
+            let path = match Self::link_under_cursor(&position, line.as_str()?) {
+                Some(link) => sov.resolve_link(&src_path, &link).ok()??,
+                None => src_path,
             };
+            // The path is used as the note name as filenames may be ambiguous
+            let backlinks = sov.resolve_backlinks(path.to_str()?, None).ok()?;
+            drop(sov);
             let mut ret = Vec::new();
-            let backlinks = self.sov.lock().unwrap().resolve_backlinks(&filename).ok()?;
             for (path, link) in backlinks {
                 let uri = Self::path_to_uri(&path).ok()?;
                 // TODO: improve this?
@@ -257,7 +282,7 @@ impl LanguageServer for SovLanguageServer {
         let cmd_res = async {
             match command {
                 "sov.index" => {
-                    self.sov.lock().unwrap().index().ok()?;
+                    self.index().await;
                     None
                 }
                 "sov.daily" => {
@@ -266,10 +291,56 @@ impl LanguageServer for SovLanguageServer {
                     let daily_path = daily_path.to_str()?.to_string();
                     Some(daily_path.into())
                 }
+                "sov.block.insert" => {
+                    let uri = Url::parse(params.arguments.first()?.as_str()?).ok()?;
+                    let line = params.arguments.get(1)?.as_u64()? as u32;
+                    let link = self.insert_block_id(&uri, line).await?;
+                    Some(link.into())
+                }
+                "sov.query" => {
+                    // Either the name of a saved query or a query expression
+                    let query = params.arguments.first()?.as_str()?;
+                    let sov = self.sov.lock().unwrap();
+                    let notes = match sov.saved_queries().contains_key(query) {
+                        true => sov.run_query(query, Default::default(), None).ok()?,
+                        false => sov.query(query, Default::default(), None).ok()?,
+                    };
+                    let notes: Vec<_> = notes
+                        .iter()
+                        .filter_map(|path| Some(Self::path_to_uri(path).ok()?.to_string()))
+                        .collect();
+                    Some(notes.into())
+                }
+                "sov.list.unlinked-mentions" => {
+                    let uri = Url::parse(params.arguments.first()?.as_str()?).ok()?;
+                    let path = Self::uri_to_path(&uri).ok()?;
+                    let mentions = self
+                        .sov
+                        .lock()
+                        .unwrap()
+                        .resolve_unlinked_mentions(path.to_str()?)
+                        .ok()?;
+                    serde_json::to_value(mentions).ok()
+                }
                 "sov.list.tags" => {
-                    let tags = self.sov.lock().unwrap().list_tags().ok()?;
+                    let tags = self
+                        .sov
+                        .lock()
+                        .unwrap()
+                        .list_tags(Default::default(), 0)
+                        .ok()?;
+                    let tags: Vec<String> = tags.into_iter().map(|t| t.tag).collect();
                     Some(tags.into())
                 }
+                "sov.list.tag_usages" => {
+                    let tags = self
+                        .sov
+                        .lock()
+                        .unwrap()
+                        .list_tags(Default::default(), 0)
+                        .ok()?;
+                    serde_json::to_value(tags).ok()
+                }
                 "sov.script.run" => {
                     let script_name = params.arguments.first()?.as_str()?;
                     let args = if params.arguments.len() > 1 {
@@ -317,6 +388,41 @@ impl LanguageServer for SovLanguageServer {
         Ok(cmd_res)
     }
 
+    /// Saved queries matching the symbol query, with one symbol per matching
+    /// note
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> Result<Option<Vec<SymbolInformation>>> {
+        let filter = params.query.to_lowercase();
+        let sov = self.sov.lock().unwrap();
+        let mut symbols = Vec::new();
+        for name in sov.saved_queries().keys() {
+            if !name.to_lowercase().contains(&filter) {
+                continue;
+            }
+            let Ok(notes) = sov.run_query(name, Default::default(), None) else {
+                continue;
+            };
+            for path in notes {
+                let Ok(uri) = Self::path_to_uri(&path) else {
+                    continue;
+                };
+                let note_name = SovNote::filename(&path).unwrap_or_default();
+                #[allow(deprecated)]
+                symbols.push(SymbolInformation {
+                    name: format!("{}: {}", name, note_name),
+                    kind: SymbolKind::FILE,
+                    tags: None,
+                    deprecated: None,
+                    location: Location::new(uri, Range::default()),
+                    container_name: Some(name.clone()),
+                });
+            }
+        }
+        Ok(Some(symbols))
+    }
+
     async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
         let uri = params.text_document_position.text_document.uri;
         let position = params.text_document_position.position;
@@ -327,14 +433,18 @@ impl LanguageServer for SovLanguageServer {
             let rope = self.document_map.get(uri.as_str())?;
             let line = rope.get_line(position.line as usize)?;
 
+            if let Some(tag) = Self::tag_at_position(&position, line.as_str()?) {
+                return self.rename_tag(&tag.name, &params.new_name);
+            }
+
             let (old_path, old_filename) =
                 if let Some(link) = Self::link_under_cursor(&position, line.as_str()?) {
                     let old_path = self.sov.lock().unwrap().resolve_note(&link.value).ok()??;
                     (old_path, link.value)
                 } else {
                     let cur_path = Self::uri_to_path(&uri).ok()?;
-                    let cur_filename = cur_path.file_stem()?.to_str()?.to_string();
-                    (cur_path, cur_filename)
+                    let cur_note = cur_path.to_str()?.to_string();
+                    (cur_path, cur_note)
                 };
 
             let new_path = self
@@ -359,9 +469,192 @@ impl LanguageServer for SovLanguageServer {
         .await;
         Ok(res)
     }
+
+    /// Offers to link the mentions of other notes in the selected range of
+    /// the current note
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let actions = async {
+            let uri = &params.text_document.uri;
+            let path = Self::uri_to_path(uri).ok()?;
+            let rope = self.document_map.get(uri.as_str())?.clone();
+            // The names are cached, the lock is not held while parsing
+            let names = self.sov.lock().unwrap().mention_names().ok()?;
+            let lines = params.range.start.line as usize..params.range.end.line as usize + 1;
+            let mentions = names.find(&path, &rope, lines);
+            let start = Self::position_to_offset(&params.range.start, &rope);
+            let end = Self::position_to_offset(&params.range.end, &rope);
+            let actions: Vec<_> = mentions
+                .into_iter()
+                .filter(|m| m.start <= end && start <= m.end + 1)
+                .map(|mention| {
+                    let range = Range::new(
+                        Self::offset_to_position(mention.start, &rope),
+                        Self::offset_to_position(mention.end + 1, &rope),
+                    );
+                    let edit = TextEdit::new(range, mention.link.clone());
+                    let changes = HashMap::from([(uri.clone(), vec![edit])]);
+                    CodeActionOrCommand::CodeAction(CodeAction {
+                        title: format!("Link {} as {}", mention.text, mention.link),
+                        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+                        edit: Some(WorkspaceEdit::new(changes)),
+                        ..Default::default()
+                    })
+                })
+                .collect();
+            Some(actions).filter(|a| !a.is_empty())
+        }
+        .await;
+        Ok(actions)
+    }
 }
 
 impl SovLanguageServer {
+    /// Indexes the notes dir while reporting the progress to the client
+    async fn index(&self) {
+        let token = NumberOrString::String("sov/index".into());
+        // The client may not support progress, indexing is done anyway
+        let _ = self
+            .client
+            .send_request::<request::WorkDoneProgressCreate>(WorkDoneProgressCreateParams {
+                token: token.clone(),
+            })
+            .await;
+        self.send_progress(
+            &token,
+            WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: "Indexing notes".into(),
+                percentage: Some(0),
+                ..Default::default()
+            }),
+        )
+        .await;
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let sov = self.sov.clone();
+        let task = tokio::task::spawn_blocking(move || {
+            sov.lock().unwrap().index_with_progress(|p| {
+                let _ = tx.send(p);
+            })
+        });
+        let mut last_percentage = 0;
+        while let Some(p) = rx.recv().await {
+            let percentage = (p.parsed * 100 / p.total.max(1)) as u32;
+            if percentage <= last_percentage {
+                continue;
+            }
+            last_percentage = percentage;
+            self.send_progress(
+                &token,
+                WorkDoneProgress::Report(WorkDoneProgressReport {
+                    message: Some(format!("{}/{}", p.parsed, p.total)),
+                    percentage: Some(percentage),
+                    ..Default::default()
+                }),
+            )
+            .await;
+        }
+        match task.await {
+            Ok(Ok(report)) => {
+                for e in report.errors {
+                    let msg = format!("failed to index {}: {}", e.path.display(), e.error);
+                    self.client.log_message(MessageType::WARNING, msg).await;
+                }
+            }
+            Ok(Err(e)) => {
+                let msg = format!("failed to index notes: {}", e);
+                self.client.log_message(MessageType::ERROR, msg).await;
+            }
+            Err(e) => {
+                let msg = format!("indexing task failed: {}", e);
+                self.client.log_message(MessageType::ERROR, msg).await;
+            }
+        }
+
+        self.send_progress(
+            &token,
+            WorkDoneProgress::End(WorkDoneProgressEnd { message: None }),
+        )
+        .await;
+    }
+
+    /// Adds a `^block-id` at the end of `line` and returns the link to it
+    async fn insert_block_id(&self, uri: &Url, line: u32) -> Option<String> {
+        let path = Self::uri_to_path(uri).ok()?;
+        let filename = path.file_stem()?.to_str()?.to_string();
+        let (id, edit) = {
+            let rope = self.document_map.get(uri.as_str())?;
+            let text = rope.to_string();
+            if !SovNote::accepts_block_id(&text, line as usize) {
+                return None;
+            }
+            let line_str = rope.get_line(line as usize)?.to_string();
+            if let Some(id) = Block::parse_id(&line_str) {
+                (id.to_string(), None)
+            } else {
+                let line_str = line_str.trim_end_matches(['\n', '\r']);
+                let blocks = SovNote::parse_blocks(&text);
+                let id = Block::new_id(&path, line as usize, &blocks);
+                let pos = Position::new(line, line_str.chars().count() as u32);
+                let edit = TextEdit::new(Range::new(pos, pos), format!(" ^{}", id));
+                (id, Some(edit))
+            }
+        };
+        if let Some(edit) = edit {
+            let changes = HashMap::from([(uri.clone(), vec![edit])]);
+            let res = self
+                .client
+                .apply_edit(WorkspaceEdit::new(changes))
+                .await
+                .ok()?;
+            if !res.applied {
+                return None;
+            }
+        }
+        Some(format!("[[{}#^{}]]", filename, id))
+    }
+
+    async fn send_progress(&self, token: &NumberOrString, progress: WorkDoneProgress) {
+        self.client
+            .send_notification::<notification::Progress>(ProgressParams {
+                token: token.clone(),
+                value: ProgressParamsValue::WorkDone(progress),
+            })
+            .await;
+    }
+
+    /// Keeps the index up to date with changes made outside of the editor
+    fn start_watcher(&self) {
+        let sov = self.sov.clone();
+        let client = self.client.clone();
+        let handle = tokio::runtime::Handle::current();
+        std::thread::spawn(move || {
+            let watcher = sov.lock().unwrap().watcher();
+            let watcher = match watcher {
+                Ok(watcher) => watcher,
+                Err(e) => {
+                    let msg = format!("failed to start watcher: {}", e);
+                    handle.block_on(client.log_message(MessageType::ERROR, msg));
+                    return;
+                }
+            };
+            while let Some(paths) = watcher.next_changes() {
+                let res = sov.lock().unwrap().index_paths(&paths);
+                match res {
+                    Ok(report) => {
+                        for e in report.errors {
+                            let msg = format!("failed to index {}: {}", e.path.display(), e.error);
+                            handle.block_on(client.log_message(MessageType::WARNING, msg));
+                        }
+                    }
+                    Err(e) => {
+                        let msg = format!("failed to index changes: {}", e);
+                        handle.block_on(client.log_message(MessageType::ERROR, msg));
+                    }
+                }
+            }
+        });
+    }
+
     async fn on_change(&self, text: &str, uri: &Url) {
         self.client
             .log_message(MessageType::ERROR, "on_change triggered!")
@@ -374,20 +667,49 @@ impl SovLanguageServer {
     async fn refresh_diagnostics(&self, uri: &Url, rope: &Rope) {
         let diagnostics = async {
             let path = Self::uri_to_path(uri).ok()?;
-            let filename = path.file_stem()?.to_str()?;
+            // The path is used as the note name as filenames may be ambiguous
+            let note = path.to_str()?;
 
-            let dead_links = self.sov.lock().unwrap().resolve_dead_links(filename).ok()?;
+            let sov = self.sov.lock().unwrap();
+            let lints = sov.lint_note(note).ok()?;
+            let inexact_links = sov.resolve_inexact_links(note).ok()?;
+            drop(sov);
             let mut diagnostics = Vec::new();
-            for dead_link in dead_links {
-                let start_pos = Self::offset_to_position(dead_link.start, rope);
-                let end_pos = Self::offset_to_position(dead_link.end, rope);
+            for lint in lints {
+                // Problems of the whole note are shown at its start
+                let range = match lint.span {
+                    Some((start, end)) => Range {
+                        start: Self::offset_to_position(start, rope),
+                        end: Self::offset_to_position(end, rope),
+                    },
+                    None => Range::default(),
+                };
+                let severity = match lint.level {
+                    LintLevel::Error => DiagnosticSeverity::ERROR,
+                    LintLevel::Warning => DiagnosticSeverity::WARNING,
+                    LintLevel::Info | LintLevel::Off => DiagnosticSeverity::INFORMATION,
+                };
+                let diagnostic = Diagnostic {
+                    range,
+                    severity: Some(severity),
+                    code: Some(NumberOrString::String(lint.rule.as_str().into())),
+                    source: Some("sov".into()),
+                    message: lint.message,
+                    ..Default::default()
+                };
+                diagnostics.push(diagnostic);
+            }
+            for (link, path) in inexact_links {
                 let diagnostic = Diagnostic {
                     range: Range {
-                        start: start_pos,
-                        end: end_pos,
+                        start: Self::offset_to_position(link.start, rope),
+                        end: Self::offset_to_position(link.end, rope),
                     },
-                    severity: Some(DiagnosticSeverity::INFORMATION),
-                    message: "Unresolved Reference".into(),
+                    severity: Some(DiagnosticSeverity::HINT),
+                    message: format!(
+                        "Reference only matches {} after case-folding or normalization",
+                        path.display()
+                    ),
                     ..Default::default()
                 };
                 diagnostics.push(diagnostic);
@@ -414,6 +736,94 @@ impl SovLanguageServer {
         Ok(path)
     }
 
+    /// Returns the partial tag before the cursor, e.g. `project/so` for
+    /// `#project/so`
+    fn tag_under_cursor(before: &str) -> Option<&str> {
+        let start = before.rfind(|c| !InlineTag::is_tag_char(c))?;
+        let tag = before[start..].strip_prefix('#')?;
+        if !before[..start].is_empty() && !before[..start].ends_with(char::is_whitespace) {
+            return None;
+        }
+        Some(tag)
+    }
+
+    /// Completes nested tags one level at a time
+    fn tag_completions(&self, tag: &str, position: &Position) -> Option<Vec<CompletionItem>> {
+        let (parent, partial) = match tag.rsplit_once(TagNode::SEPARATOR) {
+            Some((parent, partial)) => (Some(parent), partial),
+            None => (None, tag),
+        };
+        let tree = self.sov.lock().unwrap().list_tag_tree().ok()?;
+        let nodes = match parent {
+            Some(parent) => &TagNode::find(&tree, parent)?.children,
+            None => &tree,
+        };
+
+        let start = Position::new(
+            position.line,
+            position.character - partial.chars().count() as u32,
+        );
+        let range = Range::new(start, *position);
+        let completions = nodes
+            .iter()
+            .map(|node| CompletionItem {
+                label: node.name.clone(),
+                detail: Some(format!("#{} ({} notes)", node.tag, node.count)),
+                kind: Some(CompletionItemKind::CONSTANT),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                    range,
+                    node.name.clone(),
+                ))),
+                ..Default::default()
+            })
+            .collect();
+        Some(completions)
+    }
+
+    fn tag_at_position(position: &Position, line: &str) -> Option<InlineTag> {
+        let tags = SovNote::parse_tags(line);
+        tags.into_iter().find(|tag| {
+            position.character as usize >= tag.start && position.character as usize <= tag.end + 1
+        })
+    }
+
+    /// Renames a tag in all notes, the open documents are edited as they are
+    /// in the editor rather than on disk
+    fn rename_tag(&self, old_tag: &str, new_tag: &str) -> Option<WorkspaceEdit> {
+        let from = [old_tag.to_string()];
+        let rename = TagRename {
+            from: &from,
+            into: new_tag.trim_start_matches('#'),
+        };
+        let mut paths = self.sov.lock().unwrap().find_notes_by_tags(&from).ok()?;
+        // The tag may only be in unsaved changes
+        for uri in self.document_map.iter() {
+            paths.insert(Self::uri_to_path(&Url::parse(uri.key()).ok()?).ok()?);
+        }
+
+        let mut changes = HashMap::new();
+        for path in paths {
+            let uri = Self::path_to_uri(&path).ok()?;
+            let rope = match self.document_map.get(uri.as_str()) {
+                Some(rope) => rope.clone(),
+                None => Rope::from_reader(BufReader::new(File::open(&path).ok()?)).ok()?,
+            };
+            let text_edits: Vec<_> = rename
+                .edits(&rope.to_string())
+                .into_iter()
+                .map(|(range, new_text)| {
+                    let start = Self::offset_to_position(range.start, &rope);
+                    let end = Self::offset_to_position(range.end, &rope);
+                    TextEdit::new(Range::new(start, end), new_text)
+                })
+                .collect();
+            if !text_edits.is_empty() {
+                changes.insert(uri, text_edits);
+            }
+        }
+        Some(WorkspaceEdit::new(changes))
+    }
+
     fn link_under_cursor(position: &Position, line: &str) -> Option<Link> {
         let links = SovNote::parse_links(line).ok()?;
         links.into_iter().find(|link| {
@@ -421,7 +831,17 @@ impl SovLanguageServer {
         })
     }
 
+    /// Char offset of a position, clamped to the end of the buffer
+    fn position_to_offset(position: &Position, rope: &Rope) -> usize {
+        let line = (position.line as usize).min(rope.len_lines() - 1);
+        let line_len = rope.line(line).len_chars();
+        rope.line_to_char(line) + (position.character as usize).min(line_len)
+    }
+
     fn offset_to_position(offset: usize, rope: &Rope) -> Position {
+        // The offset comes from the index and may be past the end of an edited
+        // buffer
+        let offset = offset.min(rope.len_chars());
         let line = rope.char_to_line(offset);
         let character = offset - rope.line_to_char(line);
         Position::new(line as u32, character as u32)
@@ -432,7 +852,8 @@ impl SovLanguageServer {
 pub async fn main() -> Result<()> {
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
-    let sov = Sov::new().unwrap();
+    // Notes are indexed once the client is initialized to report the progress
+    let sov = Sov::load().unwrap();
     let (service, socket) = LspService::new(|client| SovLanguageServer {
         client,
         sov: Arc::new(Mutex::new(sov)),